@@ -0,0 +1,73 @@
+/// src/algebra.rs
+use crate::error::Error;
+use crate::result_expr::ResultExpr;
+use crate::wick::WickTheorem;
+
+/// Normal order every term of a sum with respect to its vacuum.
+/// The operators of every result term are sorted by
+/// `Expr::normal_order_permuted` so that equal terms merge.
+pub fn normal_order(expr: &ResultExpr) -> Result<ResultExpr, Error> {
+    let mut res = ResultExpr::new();
+    for term in expr.terms() {
+        let mut wt = WickTheorem::new(term.clone(), term.vacuum()?);
+        let ordered = wt.full_contractions(false).compute()?.result().clone();
+        for t in ordered {
+            res.push_and_merge(t.normal_order_permuted());
+        }
+    }
+    res.simplify();
+    Ok(res)
+}
+
+/// Vacuum expectation value, the fully contracted part of the normal ordered sum
+pub fn vev(expr: &ResultExpr) -> Result<ResultExpr, Error> {
+    Ok(normal_order(expr)?
+        .into_iter()
+        .filter(|t| t.ops().is_empty())
+        .collect())
+}
+
+/// [A, B] = AB - BA, normal ordered
+pub fn commutator(a: &ResultExpr, b: &ResultExpr) -> Result<ResultExpr, Error> {
+    let ab = a.try_mul(b)?;
+    let ba = b.try_mul(a)?;
+    let minus_ba: ResultExpr = ba
+        .into_iter()
+        .map(|t| {
+            let c = -t.coeff;
+            t.set_coeff(c)
+        })
+        .collect();
+    normal_order(&(ab + minus_ba))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_commutator() {
+        // [a+_p a_q, a+_r] = delta_qr a+_p
+        let a = parse("a+[p] a[q]").unwrap();
+        let b = parse("a+[r]").unwrap();
+        assert_eq!(commutator(&a, &b).unwrap().to_text(), "delta[q,r] a+[p]");
+
+        // [a+_p a_q, a+_r a_s] = delta_qr a+_p a_s - delta_ps a+_r a_q
+        let b = parse("a+[r] a[s]").unwrap();
+        assert_eq!(
+            commutator(&a, &b).unwrap().to_text(),
+            "delta[q,r] a+[p] a[s] - delta[s,p] a+[r] a[q]"
+        );
+    }
+
+    #[test]
+    fn test_vev_fermi() {
+        // <HF| a+_i a_j |HF> = delta_ij, <HF| a_a a+_b |HF> = delta_ab
+        let e = parse("f[i,j] a+[i] a[j] + f[a,b] a[a] a+[b] + f[i,a] a+[i] a[a]").unwrap();
+        assert_eq!(
+            vev(&e).unwrap().to_text(),
+            "delta[i,j] f[i,j] + delta[a,b] f[a,b]"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+/// src/archive.rs
+use crate::error::Error;
+use crate::result_expr::ResultExpr;
+use serde::{Deserialize, Serialize};
+
+/// Version of the stored format, bumped on every incompatible change.
+/// Archives written with an older version keep loading in later crates.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A stored derivation: the result tagged with the format and crate versions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub format_version: u32,
+    pub crate_version: String,
+    pub result: ResultExpr,
+}
+
+impl Archive {
+    pub fn new(result: ResultExpr) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            result,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    /// Load an archive, rejecting formats newer than this crate knows
+    pub fn from_json(s: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct Header {
+            format_version: u32,
+        }
+        let failed = |e: serde_json::Error| Error::Serialization(e.to_string());
+        let header: Header = serde_json::from_str(s).map_err(failed)?;
+        if header.format_version > FORMAT_VERSION {
+            return Err(Error::UnsupportedFormat {
+                version: header.format_version,
+                supported: FORMAT_VERSION,
+            });
+        }
+        serde_json::from_str(s).map_err(failed)
+    }
+}
+
+impl ResultExpr {
+    /// Serialize into a versioned JSON archive
+    pub fn to_json(&self) -> Result<String, Error> {
+        Archive::new(self.clone()).to_json()
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, Error> {
+        Archive::from_json(s).map(|a| a.result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_json_round_trip() {
+        let e = parse("0.5 * f[i,a] a+[a] a[i] - delta[i,j] t[i,x:v]").unwrap();
+        let json = e.to_json().unwrap();
+        assert!(json.contains("\"format_version\": 1"));
+        let back = ResultExpr::from_json(&json).unwrap();
+        assert_eq!(back.to_text(), e.to_text());
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let json = ResultExpr::new().to_json().unwrap();
+        let newer = json.replace("\"format_version\": 1", "\"format_version\": 99");
+        assert!(matches!(
+            ResultExpr::from_json(&newer),
+            Err(Error::UnsupportedFormat { version: 99, .. })
+        ));
+    }
+}
@@ -1,5 +1,4 @@
 /// src/attr.rs
-
 /// Definitions of attributes used in the library
 /// Vacuum is used to define the reference state for operators
 /// Physical is the true vacuum |0|
@@ -7,7 +6,8 @@
 /// Fermi is the Fermi vacuum |HF|
 /// a^dagger |HF| = 0, a |HF| = 0
 /// The Multireference vacuum I dont know...
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Vacuum {
     Physical,       // |0|
     Fermi,          // |HF|
@@ -19,16 +19,41 @@ pub enum Vacuum {
 /// Occupied: i, j, k
 /// Virtual: a, b, c
 /// DoublyOccupied: core / frozen core
+/// These four are built in, more can be defined with `Space::define` and
+/// `Space::union`. A Space is a Copy id into the space registry, which keeps
+/// every space as a set of disjoint atoms, so inclusion and overlap are
+/// plain set relations. Spaces are ordered by registration.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub struct Space(u16);
+
+#[allow(non_upper_case_globals)]
+impl Space {
+    pub const General: Space = Space(0); // p, q, r, s
+    pub const Occupied: Space = Space(1); // i, j, k
+    pub const Virtual: Space = Space(2); // a, b, c
+    pub const DoublyOccupied: Space = Space(3); // core / frozen core
+}
+
+/// Occupation of the orbitals of an atomic space in the reference
+/// Hole: occupied, Particle: empty, Active: partially occupied (MR),
+/// Auxiliary: not an orbital space at all, e.g. an RI basis
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum Space {
-    General,        // p, q, r, s
-    Occupied,       // i, j, k
-    Virtual,        // a, b, c
-    DoublyOccupied, // core / frozen core
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Occupancy {
+    Hole,
+    Particle,
+    Active,
+    Auxiliary,
 }
 
 /// Just 2nd-quantization operator actions: creation and annihilation
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Action {
     Create,
     Annihilate,
@@ -36,12 +61,15 @@ pub enum Action {
 /// Statistics of the particles: Fermions, Bosons, or Arbitrary
 /// I want to support Bosons in the future
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Statistics {
     FermiDirac,
     BoseEinstein,
     Arbitrary,
 }
 
+use crate::error::Error;
+use crate::registry::{self, SpaceData};
 use std::fmt;
 
 impl fmt::Display for Vacuum {
@@ -56,26 +84,104 @@ impl fmt::Display for Vacuum {
 }
 
 /// Check if the space is allowed for the vacuum
-/// General space is only allowed for Physical vacuum
+/// General space is only allowed for Physical vacuum,
+/// the Fermi vacuum needs a definite occupation that is not Active
 impl Space {
-    pub const fn is_allowed(self, v: Vacuum) -> bool {
+    pub fn is_allowed(self, v: Vacuum) -> bool {
         match v {
-            Vacuum::Physical => matches!(self, Self::General),
-            Vacuum::Fermi => !matches!(self, Self::General),
+            Vacuum::Physical => self == Self::General,
+            Vacuum::Fermi => self.occupancy().is_some_and(|o| o != Occupancy::Active),
             Vacuum::MultiReference => true,
         }
     }
+
+    /// New space of fresh orbitals inside `parent`, disjoint from every
+    /// space defined so far that does not contain `parent`
+    pub fn define(name: &str, parent: Space, occupancy: Occupancy) -> Result<Space, Error> {
+        registry::define_space(name, parent, occupancy)
+    }
+
+    /// New space made of the orbitals of `parts`
+    pub fn union(name: &str, parts: &[Space]) -> Result<Space, Error> {
+        registry::union_space(name, parts)
+    }
+
+    /// Space registered under `name`
+    pub fn lookup(name: &str) -> Option<Space> {
+        registry::lookup_space(name)
+    }
+
+    /// Every orbital of `other` is in `self`
+    pub fn includes(self, other: Space) -> bool {
+        let (a, b) = (self.mask(), other.mask());
+        b & !a == 0
+    }
+
+    pub fn is_disjoint(self, other: Space) -> bool {
+        self.mask() & other.mask() == 0
+    }
+
+    /// Common orbitals, registered as `a&b` on first use, None when disjoint
+    pub fn intersection(self, other: Space) -> Result<Option<Space>, Error> {
+        registry::intersect_spaces(self, other)
+    }
+
+    /// Occupation shared by every orbital, None for a mix like General
+    pub fn occupancy(self) -> Option<Occupancy> {
+        registry::space_occupancy(self)
+    }
+
+    /// Quasi-annihilated by a^+ on the Fermi vacuum
+    pub fn is_hole(self) -> bool {
+        self.occupancy() == Some(Occupancy::Hole)
+    }
+    pub fn is_particle(self) -> bool {
+        self.occupancy() == Some(Occupancy::Particle)
+    }
+
+    /// Some interface
+    pub fn name(self) -> &'static str {
+        self.data().name
+    }
+    pub fn data(self) -> SpaceData {
+        registry::space_data(self)
+    }
+    /// Atoms of the space, one bit each
+    pub fn mask(self) -> u64 {
+        registry::space_mask(self)
+    }
+    pub fn id(self) -> u16 {
+        self.0
+    }
+    pub(crate) const fn from_id(id: u16) -> Self {
+        Self(id)
+    }
+}
+
+impl fmt::Debug for Space {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
 }
 
 impl fmt::Display for Space {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = match self {
-            Self::General => "GeneralSpace",
-            Self::Occupied => "OccupiedSpace",
-            Self::Virtual => "VirtualSpace",
-            Self::DoublyOccupied => "DoublyOccupiedSpace",
-        };
-        write!(f, "{}", s)
+        write!(f, "{}Space", self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Space> for String {
+    fn from(space: Space) -> Self {
+        space.name().to_string()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<String> for Space {
+    type Error = String;
+    fn try_from(name: String) -> Result<Self, String> {
+        Space::lookup(&name).ok_or_else(|| format!("unknown space {}", name))
     }
 }
 
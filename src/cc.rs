@@ -0,0 +1,437 @@
+/// src/cc.rs
+use crate::attr::{Action, Space, Vacuum};
+use crate::expr::Expr;
+use crate::format::fraction;
+use crate::index::Index;
+use crate::op::{Op, fannx, fcrex};
+use crate::result_expr::ResultExpr;
+use crate::tensor::Tensor;
+use crate::topology::{Generator, VertexType};
+use crate::wick::WickTheorem;
+use std::collections::HashMap;
+
+/// Coupled-cluster equations on the Fermi vacuum
+/// E   = <HF| e^{-T} H e^{T} |HF>
+/// R_m = <HF_{i..}^{a..}| e^{-T} H e^{T} |HF>, m = 1..rank
+/// with the normal ordered Hamiltonian
+/// H = f[p,q] {a+[p] a[q]} + 1/4 g[p,q,r,s] {a+[p] a+[q] a[s] a[r]}
+/// (g is the antisymmetrized integral <pq||rs>) and
+/// T_n = 1/(n!)^2 tn[a_1..a_n,i_1..i_n] a+[a_1]..a+[a_n] a[i_n]..a[i_1].
+/// Only connected terms survive, so the BCH series is summed as (H e^T)_c.
+pub struct CoupledCluster {
+    rank: usize,
+}
+
+impl CoupledCluster {
+    /// Truncation of the cluster operator, 2 for CCSD
+    pub fn new(rank: usize) -> Self {
+        Self { rank }
+    }
+
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    pub fn energy(&self) -> ResultExpr {
+        self.projected(0)
+    }
+
+    /// Residual of the excitation rank m, with free indices `external_indices(m)`
+    pub fn residual(&self, m: usize) -> ResultExpr {
+        self.projected(m)
+    }
+
+    /// Free indices of R_m, in the order of the amplitude tm: a_1..a_m, i_1..i_m
+    pub fn external_indices(m: usize) -> Vec<Index> {
+        let mut v: Vec<Index> = (0..m)
+            .map(|k| vir(&external_name(Space::Virtual, k)))
+            .collect();
+        v.extend((0..m).map(|k| occ(&external_name(Space::Occupied, k))));
+        v
+    }
+
+    fn projected(&self, m: usize) -> ResultExpr {
+        let mut res = ResultExpr::new();
+        let bra = projector(m);
+        for h in hamiltonian() {
+            let exc_h = excitation(&h.ops);
+            for ranks in cluster_products(self.rank, 4) {
+                let total: i32 = ranks.iter().map(|&k| k as i32).sum();
+                if exc_h + total != m as i32 {
+                    continue;
+                }
+                // 1/n! of e^T, split into 1/prod(m_k!) over distinct ranks
+                let mut weight = 1.0;
+                for k in 1..=self.rank {
+                    weight /= factorial(ranks.iter().filter(|&&r| r == k).count());
+                }
+                let mut vertices = vec![bra.clone(), h.clone()];
+                let mut counter = 0;
+                for &k in &ranks {
+                    vertices.push(amplitude(k, &mut counter));
+                }
+                for term in contract(&vertices) {
+                    let c = term.coeff * weight;
+                    res.push_and_merge(canonicalize(term.set_coeff(c), m));
+                }
+            }
+        }
+        snap(&mut res);
+        res
+    }
+
+    /// Same as `energy` and `residual`, from the diagrammatic generator
+    pub fn diagrammatic(&self, m: usize) -> ResultExpr {
+        Generator::new(vec![VertexType::Fock, VertexType::TwoBody], self.rank).projected(m)
+    }
+}
+
+/// The 1/n! weights are summed in floating point, snap back to fractions
+pub(crate) fn snap(res: &mut ResultExpr) {
+    *res = std::mem::take(res)
+        .into_iter()
+        .map(|mut t| {
+            if let Some((n, d)) = fraction(t.coeff) {
+                t.coeff = n as f64 / d as f64;
+            }
+            t
+        })
+        .collect();
+    res.simplify();
+}
+
+pub(crate) fn occ(name: &str) -> Index {
+    Index::new(name)
+        .with_space(Space::Occupied)
+        .with_vacuum(Vacuum::Fermi)
+        .build()
+        .unwrap()
+}
+
+pub(crate) fn vir(name: &str) -> Index {
+    Index::new(name)
+        .with_space(Space::Virtual)
+        .with_vacuum(Vacuum::Fermi)
+        .build()
+        .unwrap()
+}
+
+/// i, j, k, l, i5, ... and a, b, c, d, a5, ...
+pub(crate) fn external_name(space: Space, k: usize) -> String {
+    let (letters, stem) = match space {
+        Space::Virtual => (["a", "b", "c", "d"], "a"),
+        _ => (["i", "j", "k", "l"], "i"),
+    };
+    match letters.get(k) {
+        Some(l) => l.to_string(),
+        None => format!("{}{}", stem, k + 1),
+    }
+}
+
+/// m, n, o, m1, n1, ... and e, f, g, h, e1, ...
+fn dummy_name(space: Space, k: usize) -> String {
+    let letters: &[&str] = match space {
+        Space::Virtual => &["e", "f", "g", "h"],
+        _ => &["m", "n", "o"],
+    };
+    let l = letters[k % letters.len()];
+    match k / letters.len() {
+        0 => l.to_string(),
+        n => format!("{}{}", l, n),
+    }
+}
+
+/// <HF| a+[i_1]..a+[i_m] a[a_m]..a[a_1]
+fn projector(m: usize) -> Expr {
+    let mut e = Expr::new();
+    for k in 0..m {
+        e.append_op(fcrex(occ(&external_name(Space::Occupied, k))));
+    }
+    for k in (0..m).rev() {
+        e.append_op(fannx(vir(&external_name(Space::Virtual, k))));
+    }
+    e
+}
+
+/// Every occupied / virtual block of the one- and two-body Hamiltonian
+fn hamiltonian() -> Vec<Expr> {
+    let spaces = [Space::Occupied, Space::Virtual];
+    let index = |s: Space, name: &str| match s {
+        Space::Virtual => vir(&format!("e{}", name)),
+        _ => occ(&format!("m{}", name)),
+    };
+    let mut h = Vec::new();
+    for p in spaces {
+        for q in spaces {
+            let (p, q) = (index(p, "p"), index(q, "q"));
+            h.push(Tensor::new("f", vec![p, q]) * fcrex(p) * fannx(q));
+        }
+    }
+    for p in spaces {
+        for q in spaces {
+            for r in spaces {
+                for s in spaces {
+                    let (p, q, r, s) = (index(p, "p"), index(q, "q"), index(r, "r"), index(s, "s"));
+                    let g = Tensor::new("g", vec![p, q, r, s]) * fcrex(p);
+                    h.push((g * fcrex(q) * fannx(s) * fannx(r)).set_coeff(0.25));
+                }
+            }
+        }
+    }
+    h
+}
+
+/// T_k with fresh dummy indices
+fn amplitude(k: usize, counter: &mut usize) -> Expr {
+    let start = *counter;
+    *counter += k;
+    let virs: Vec<Index> = (start..start + k)
+        .map(|n| vir(&format!("e{}", n)))
+        .collect();
+    let occs: Vec<Index> = (start..start + k)
+        .map(|n| occ(&format!("m{}", n)))
+        .collect();
+    let ops: Vec<Op> = virs
+        .iter()
+        .cloned()
+        .map(fcrex)
+        .chain(occs.iter().rev().cloned().map(fannx))
+        .collect();
+    let mut indices = virs;
+    indices.extend(occs);
+    let mut e = (1.0 / (factorial(k) * factorial(k))) * Tensor::new(format!("t{}", k), indices);
+    for op in ops {
+        e.append_op(op);
+    }
+    e
+}
+
+/// Excitation level of an operator string on the Fermi vacuum
+fn excitation(ops: &[Op]) -> i32 {
+    let twice: i32 = ops
+        .iter()
+        .map(|o| match (o.space().is_particle(), o.action) {
+            (true, Action::Create) => 1,
+            (true, Action::Annihilate) => -1,
+            (_, Action::Annihilate) => 1,
+            (_, Action::Create) => -1,
+        })
+        .sum();
+    twice / 2
+}
+
+/// Non-increasing lists of cluster ranks with at most `max_len` entries
+pub(crate) fn cluster_products(rank: usize, max_len: usize) -> Vec<Vec<usize>> {
+    fn rec(max_rank: usize, left: usize, cur: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        out.push(cur.clone());
+        if left == 0 {
+            return;
+        }
+        for k in 1..=max_rank {
+            cur.push(k);
+            rec(k, left - 1, cur, out);
+            cur.pop();
+        }
+    }
+    let mut out = Vec::new();
+    rec(rank, max_len, &mut Vec::new(), &mut out);
+    out
+}
+
+pub(crate) fn factorial(n: usize) -> f64 {
+    (1..=n).map(|k| k as f64).product()
+}
+
+/// Fully contracted terms of the product of the vertices bra, H, T...
+/// Every vertex is brought into normal order first, so `WickTheorem` only
+/// pairs operators of different vertices; every amplitude (vertex 2..)
+/// must be contracted with the Hamiltonian (vertex 1).
+fn contract(vertices: &[Expr]) -> Vec<Expr> {
+    let mut owner: Vec<usize> = Vec::new();
+    let mut product = Expr::new();
+    for (v, vertex) in vertices.iter().enumerate() {
+        let vertex = vertex.normal_order_permuted();
+        owner.extend(vertex.ops.iter().map(|_| v));
+        product = product * vertex;
+    }
+
+    let vacuum = product
+        .vacuum()
+        .expect("cc vertices share the Fermi vacuum");
+    let mut wt = WickTheorem::new(product, vacuum);
+    wt.full_contractions(true);
+    let pairings = wt
+        .pairings()
+        .expect("cc vertices are built on the Fermi vacuum");
+    pairings
+        .filter(|p| 2 * p.len() == owner.len())
+        .filter(|p| {
+            (2..vertices.len()).all(|t| {
+                p.iter()
+                    .any(|&(x, y)| owner[x] == 1 && owner[y] == t || owner[y] == 1 && owner[x] == t)
+            })
+        })
+        .map(|p| wt.step(p).term)
+        .collect()
+}
+
+/// Remove the deltas, then name the dummies in a canonical order and sort
+/// the antisymmetric index groups g[pq,rs], tn[a..,i..] with sign.
+/// Every dummy joins the Hamiltonian tensor (the first one) to an amplitude.
+/// Amplitudes are ordered by label and by what each of their index groups
+/// holds, externals and the Hamiltonian groups of their dummies, amplitudes
+/// alike in both are interchangeable. The dummies of the Hamiltonian are
+/// then named group by group in the order of the amplitude they lead to.
+pub(crate) fn canonicalize(mut e: Expr, m: usize) -> Expr {
+    let external: Vec<&str> = CoupledCluster::external_indices(m)
+        .into_iter()
+        .map(|i| i.name())
+        .collect();
+    let is_external = |n: &str| external.contains(&n);
+
+    // delta_xy: replace the dummy by the other index
+    let mut map: HashMap<&str, Index> = HashMap::new();
+    let resolve = |map: &HashMap<&str, Index>, i: &Index| {
+        let mut i = *i;
+        while let Some(&j) = map.get(i.name()) {
+            i = j;
+        }
+        i
+    };
+    for d in std::mem::take(&mut e.deltas) {
+        let (a, b) = (resolve(&map, &d.a), resolve(&map, &d.b));
+        if a.name() == b.name() {
+            continue;
+        }
+        let (keep, drop) =
+            if is_external(a.name()) || (!is_external(b.name()) && a.name() < b.name()) {
+                (a, b)
+            } else {
+                (b, a)
+            };
+        map.insert(drop.name(), keep);
+    }
+    for t in &mut e.tensors {
+        for i in &mut t.indices {
+            *i = resolve(&map, i);
+        }
+    }
+
+    // Index group of every slot, the first half of a tensor or the second
+    let group = |t: &Tensor, k: usize| usize::from(2 * k >= t.indices.len());
+    let h_groups: HashMap<&str, usize> = e.tensors[0]
+        .indices
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| !is_external(i.name()))
+        .map(|(k, i)| (i.name(), group(&e.tensors[0], k)))
+        .collect();
+    let describe = |t: &Tensor| {
+        let mut groups: [Vec<(usize, usize, &str)>; 2] = [Vec::new(), Vec::new()];
+        for (k, i) in t.indices.iter().enumerate() {
+            groups[group(t, k)].push(match h_groups.get(i.name()) {
+                Some(&g) => (1, g, ""),
+                None if is_external(i.name()) => (0, 0, i.name()),
+                None => (2, 0, ""),
+            });
+        }
+        for g in &mut groups {
+            g.sort_unstable();
+        }
+        (t.label.clone(), groups)
+    };
+    e.tensors[1..].sort_by_cached_key(describe);
+
+    // Hamiltonian dummies by (group, amplitude, group there), then the rest
+    let mut order: Vec<((usize, usize, usize), &'static str)> = Vec::new();
+    for (x, t) in e.tensors.iter().enumerate().skip(1) {
+        for (k, i) in t.indices.iter().enumerate() {
+            if let Some(&g) = h_groups.get(i.name()) {
+                order.push(((g, x, group(t, k)), i.name()));
+            }
+        }
+    }
+    order.sort_by_key(|(key, _)| *key);
+    let mut names: HashMap<&'static str, String> = HashMap::new();
+    let mut counter = [0, 0];
+    let all: Vec<Index> = e.tensors.iter().flat_map(|t| t.indices.clone()).collect();
+    let h_first = order
+        .iter()
+        .filter_map(|(_, n)| all.iter().find(|i| i.name() == *n));
+    for i in h_first.chain(&all) {
+        if is_external(i.name()) || names.contains_key(i.name()) {
+            continue;
+        }
+        let k = usize::from(i.space() == Space::Virtual);
+        names.insert(i.name(), dummy_name(i.space(), counter[k]));
+        counter[k] += 1;
+    }
+
+    let mut sign = 1.0;
+    for t in &mut e.tensors {
+        for i in &mut t.indices {
+            if let Some(n) = names.get(i.name()) {
+                *i = i.renamed(n);
+            }
+        }
+        let half = t.indices.len() / 2;
+        for range in [0..half, half..t.indices.len()] {
+            let group = &mut t.indices[range];
+            for i in 1..group.len() {
+                let mut j = i;
+                while j > 0 && group[j - 1].name() > group[j].name() {
+                    group.swap(j - 1, j);
+                    sign = -sign;
+                    j -= 1;
+                }
+            }
+        }
+    }
+    e.tensors[1..].sort_by_key(|t| t.sort_key().1);
+    e.tensors[1..].sort_by(|a, b| a.label.cmp(&b.label));
+    e.coeff *= sign;
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ccsd_energy() {
+        let e = CoupledCluster::new(2).energy();
+        assert_eq!(
+            e.to_text(),
+            "f[m,e] t1[e,m] + 0.5 * g[m,n,e,f] t1[e,m] t1[f,n] + 0.25 * g[m,n,e,f] t2[e,f,m,n]"
+        );
+    }
+
+    #[test]
+    fn test_ccd_doubles_driver() {
+        // The amplitude-free part of the CCD residual is <ab||ij>
+        let r = CoupledCluster::new(2).residual(2);
+        let bare: Vec<_> = r.terms().iter().filter(|t| t.tensors.len() == 1).collect();
+        assert_eq!(bare.len(), 1);
+        assert_eq!(bare[0].to_text(), "g[a,b,i,j]");
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        // Swapping the dummies of either space, or the two t1, is the same term
+        let forms = [
+            "g[m,n,e,f] t1[e,m] t1[f,n]",
+            "-1 * g[n,m,e,f] t1[e,m] t1[f,n]",
+            "g[n,m,f,e] t1[f,n] t1[e,m]",
+            "g[o,m,h,e] t1[h,o] t1[e,m]",
+        ];
+        for f in forms {
+            let t = canonicalize(crate::parser::parse_term(f).unwrap(), 0);
+            assert_eq!(t.to_text(), "g[m,n,e,f] t1[e,m] t1[f,n]");
+        }
+        let t = canonicalize(
+            crate::parser::parse_term("g[n,m,e,f] t1[e,m] t1[f,n]").unwrap(),
+            0,
+        );
+        assert_eq!(t.to_text(), "-g[m,n,e,f] t1[e,m] t1[f,n]");
+    }
+}
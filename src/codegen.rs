@@ -0,0 +1,747 @@
+/// src/codegen.rs
+use crate::attr::Space;
+use crate::error::Error;
+use crate::expr::Expr;
+use crate::factorize::Factorization;
+use crate::index::Index;
+use crate::op::Delta;
+use crate::result_expr::ResultExpr;
+use crate::tensor::Tensor;
+use std::collections::BTreeSet;
+
+/// Target language of the generated code
+/// Einsum: a Python function built on numpy.einsum
+/// Rust: a Rust function with nested loops over flat Vec<f64>
+/// Cpp: a C++ function template with TiledArray-like tensor expressions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    Einsum,
+    Rust,
+    Cpp,
+}
+
+impl Target {
+    /// Start of a file holding any number of emitted functions
+    pub fn prelude(self) -> &'static str {
+        match self {
+            Self::Einsum => "import numpy as np\n\n\n",
+            Self::Rust | Self::Cpp => "",
+        }
+    }
+}
+
+/// CodeGen turns a ResultExpr (or a Factorization of it) into code computing
+/// the tensor `name` with axes `output`.
+/// Input tensors are expected over the full orbital range (nocc + nvir per axis,
+/// occupied first) and are sliced by the space of each index.
+/// Intermediates and the output only span the spaces of their indices.
+pub struct CodeGen {
+    name: String,
+    output: Vec<Index>,
+    antisymmetrize: Vec<(Index, Index)>,
+}
+
+/// One tensor assignment: lhs (+)= coeff * tensors * deltas
+struct Statement {
+    lhs: Tensor,
+    accumulate: bool,
+    coeff: f64,
+    tensors: Vec<Tensor>,
+    deltas: Vec<Delta>,
+}
+
+/// Statements of the terms sharing the permutation over `pairs`
+struct PermGroup {
+    key: Vec<(&'static str, &'static str)>,
+    pairs: Vec<(Index, Index)>,
+    stmts: Vec<Statement>,
+}
+
+impl CodeGen {
+    pub fn new(name: impl Into<String>, output: Vec<Index>) -> Self {
+        Self {
+            name: name.into(),
+            output,
+            antisymmetrize: Vec::new(),
+        }
+    }
+
+    /// Antisymmetrize the result in a pair of output indices once all terms
+    /// are summed, R <- R - P(ab) R
+    pub fn antisymmetrize(mut self, a: Index, b: Index) -> Self {
+        self.antisymmetrize.push((a, b));
+        self
+    }
+
+    /// The function computing `expr`, without `Target::prelude`
+    pub fn emit(&self, expr: &ResultExpr, target: Target) -> Result<String, Error> {
+        let stmts = self.term_statements(expr.terms())?;
+        self.emit_statements(&stmts, target)
+    }
+
+    pub fn emit_factorized(&self, f: &Factorization, target: Target) -> Result<String, Error> {
+        let mut stmts: Vec<Statement> = f
+            .intermediates()
+            .iter()
+            .map(|x| Statement {
+                lhs: x.tensor.clone(),
+                accumulate: false,
+                coeff: 1.0,
+                tensors: vec![x.left.clone(), x.right.clone()],
+                deltas: Vec::new(),
+            })
+            .collect();
+        stmts.extend(self.term_statements(f.terms())?);
+        self.emit_statements(&stmts, target)
+    }
+
+    /// One accumulation per term. Terms with the same permutation operators
+    /// are summed as they are into the temporary `{name}_p{k}`, which is
+    /// added to the result once per image of the permutation.
+    fn term_statements(&self, terms: &[Expr]) -> Result<Vec<Statement>, Error> {
+        let lhs = Tensor::new(self.name.clone(), self.output.clone());
+        let mut stmts = Vec::new();
+        let mut groups: Vec<PermGroup> = Vec::new();
+        for t in terms {
+            if let Some(p) = t.placeholders().first() {
+                return Err(Error::UnexpandedPlaceholder {
+                    name: p.name.clone(),
+                });
+            }
+            if !t.ops().is_empty() {
+                return Err(Error::UncontractedOperators {
+                    term: t.to_latex(),
+                    operation: "Code generation",
+                });
+            }
+            let mut st = Statement {
+                lhs: lhs.clone(),
+                accumulate: true,
+                coeff: t.coeff(),
+                tensors: t.tensors().to_vec(),
+                deltas: t.deltas().to_vec(),
+            };
+            if t.perms.is_empty() {
+                stmts.push(st);
+                continue;
+            }
+            let mut key: Vec<_> = t.perms.iter().flat_map(|p| p.sort_key()).collect();
+            key.sort_unstable();
+            let k = match groups.iter().position(|g| g.key == key) {
+                Some(k) => k,
+                None => {
+                    let pairs: Vec<(Index, Index)> =
+                        t.perms.iter().flat_map(|p| p.pairs.clone()).collect();
+                    for i in pairs.iter().flat_map(|(a, b)| [a, b]) {
+                        if !self.output.iter().any(|o| o.name() == i.name()) {
+                            return Err(Error::NotAnOutput { index: *i });
+                        }
+                    }
+                    groups.push(PermGroup {
+                        key,
+                        pairs,
+                        stmts: Vec::new(),
+                    });
+                    groups.len() - 1
+                }
+            };
+            st.lhs = Tensor::new(format!("{}_p{}", self.name, k), self.output.clone());
+            st.accumulate = !groups[k].stmts.is_empty();
+            groups[k].stmts.push(st);
+        }
+
+        for group in groups {
+            let temp = group.stmts[0].lhs.label.clone();
+            stmts.extend(group.stmts);
+            // P(ij) = 1 - (i <-> j), every pair doubles the images
+            let mut images = vec![(1.0, self.output.clone())];
+            for (a, b) in &group.pairs {
+                let swapped: Vec<(f64, Vec<Index>)> = images
+                    .iter()
+                    .map(|(c, ix)| {
+                        let pos = |i: &Index| ix.iter().position(|o| o.name() == i.name());
+                        let mut ix = ix.clone();
+                        if let (Some(x), Some(y)) = (pos(a), pos(b)) {
+                            ix.swap(x, y);
+                        }
+                        (-c, ix)
+                    })
+                    .collect();
+                images.extend(swapped);
+            }
+            for (coeff, ix) in images {
+                stmts.push(Statement {
+                    lhs: lhs.clone(),
+                    accumulate: true,
+                    coeff,
+                    tensors: vec![Tensor::new(temp.clone(), ix)],
+                    deltas: Vec::new(),
+                });
+            }
+        }
+        Ok(stmts)
+    }
+
+    /// Every lhs other than the result is an intermediate
+    fn emit_statements(&self, stmts: &[Statement], target: Target) -> Result<String, Error> {
+        for idx in self.indices(stmts) {
+            if !matches!(
+                idx.space(),
+                Space::Occupied | Space::Virtual | Space::General
+            ) {
+                return Err(Error::UnsupportedSpace {
+                    space: idx.space(),
+                    operation: "Code generation",
+                });
+            }
+        }
+        for (a, b) in &self.antisymmetrize {
+            for i in [a, b] {
+                if !self.output.iter().any(|o| o.name() == i.name()) {
+                    return Err(Error::NotAnOutput { index: *i });
+                }
+            }
+        }
+        let intermediates: BTreeSet<String> = stmts
+            .iter()
+            .map(|s| s.lhs.label.clone())
+            .filter(|l| *l != self.name)
+            .collect();
+        let inputs: BTreeSet<String> = stmts
+            .iter()
+            .flat_map(|s| &s.tensors)
+            .filter(|t| !intermediates.contains(&t.label))
+            .map(|t| t.label.clone())
+            .collect();
+        match target {
+            Target::Einsum => self.emit_einsum(stmts, &inputs),
+            Target::Rust => Ok(self.emit_rust(stmts, &inputs)),
+            Target::Cpp => Ok(self.emit_cpp(stmts, &intermediates)),
+        }
+    }
+
+    fn indices<'a>(&'a self, stmts: &'a [Statement]) -> impl Iterator<Item = &'a Index> {
+        stmts
+            .iter()
+            .flat_map(|s| {
+                s.lhs
+                    .indices
+                    .iter()
+                    .chain(s.tensors.iter().flat_map(|t| &t.indices))
+                    .chain(s.deltas.iter().flat_map(|d| [&d.a, &d.b]))
+            })
+            .chain(&self.output)
+    }
+
+    /// Position of each antisymmetrized pair in the output
+    fn swapped_axes(&self) -> Vec<(usize, usize)> {
+        let pos = |i: &Index| {
+            self.output
+                .iter()
+                .position(|o| o.name() == i.name())
+                .unwrap()
+        };
+        self.antisymmetrize
+            .iter()
+            .map(|(a, b)| (pos(a), pos(b)))
+            .collect()
+    }
+}
+
+// --- numpy.einsum ---
+
+impl CodeGen {
+    fn emit_einsum(&self, stmts: &[Statement], inputs: &BTreeSet<String>) -> Result<String, Error> {
+        let mut args: Vec<String> = inputs.iter().cloned().collect();
+        args.push("nocc".to_string());
+        args.push("nvir".to_string());
+
+        let mut s = format!("def compute_{}({}):\n", self.name, args.join(", "));
+        s.push_str("    nmo = nocc + nvir\n");
+        s.push_str("    o, v = slice(0, nocc), slice(nocc, nmo)\n");
+        let shape: Vec<&str> = self.output.iter().map(|i| dim_name(i.space())).collect();
+        s.push_str(&format!(
+            "    {} = np.zeros(({}))\n",
+            self.name,
+            tuple(&shape)
+        ));
+
+        for st in stmts {
+            let letters = einsum_letters(st)?;
+            let letter = |i: &Index| letters.iter().find(|(n, _)| *n == i.name()).unwrap().1;
+            let mut specs = Vec::new();
+            let mut operands = Vec::new();
+            for t in &st.tensors {
+                specs.push(t.indices.iter().map(letter).collect::<String>());
+                if inputs.contains(&t.label) {
+                    let slices: Vec<&str> =
+                        t.indices.iter().map(|i| slice_name(i.space())).collect();
+                    operands.push(format!("{}[{}]", t.label, slices.join(", ")));
+                } else {
+                    operands.push(t.label.clone());
+                }
+            }
+            for d in &st.deltas {
+                specs.push(format!("{}{}", letter(&d.a), letter(&d.b)));
+                operands.push(format!(
+                    "np.eye(nmo)[{}, {}]",
+                    slice_name(d.a.space()),
+                    slice_name(d.b.space())
+                ));
+            }
+
+            let value = if operands.is_empty() {
+                format!("{:?}", st.coeff)
+            } else {
+                let out: String = st.lhs.indices.iter().map(letter).collect();
+                let call = format!(
+                    "np.einsum('{}->{}', {})",
+                    specs.join(","),
+                    out,
+                    operands.join(", ")
+                );
+                if st.coeff == 1.0 {
+                    call
+                } else {
+                    format!("{:?} * {}", st.coeff, call)
+                }
+            };
+            let op = if st.accumulate { "+=" } else { "=" };
+            s.push_str(&format!("    {} {} {}\n", st.lhs.label, op, value));
+        }
+
+        for (a, b) in self.swapped_axes() {
+            let mut perm: Vec<String> = (0..self.output.len()).map(|k| k.to_string()).collect();
+            perm.swap(a, b);
+            s.push_str(&format!(
+                "    {0} = {0} - {0}.transpose({1})\n",
+                self.name,
+                tuple(&perm)
+            ));
+        }
+        s.push_str(&format!("    return {}\n", self.name));
+        Ok(s)
+    }
+}
+
+/// Single letter for every index of a statement, keeping one-letter names
+fn einsum_letters(st: &Statement) -> Result<Vec<(String, char)>, Error> {
+    const LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let mut names: Vec<&str> = Vec::new();
+    let all = st
+        .lhs
+        .indices
+        .iter()
+        .chain(st.tensors.iter().flat_map(|t| &t.indices))
+        .chain(st.deltas.iter().flat_map(|d| [&d.a, &d.b]));
+    for i in all {
+        if !names.contains(&i.name()) {
+            names.push(i.name());
+        }
+    }
+    if names.len() > LETTERS.len() {
+        return Err(Error::TooManyIndices {
+            count: names.len(),
+            limit: LETTERS.len(),
+        });
+    }
+
+    let single = |n: &str| {
+        let mut c = n.chars();
+        match (c.next(), c.next()) {
+            (Some(ch), None) if ch.is_ascii_alphabetic() => Some(ch),
+            _ => None,
+        }
+    };
+    let taken: Vec<char> = names.iter().filter_map(|n| single(n)).collect();
+    let mut free = LETTERS.chars().filter(|c| !taken.contains(c));
+    Ok(names
+        .iter()
+        .map(|n| {
+            (
+                n.to_string(),
+                single(n).unwrap_or_else(|| free.next().unwrap()),
+            )
+        })
+        .collect())
+}
+
+// --- Rust loops ---
+
+impl CodeGen {
+    fn emit_rust(&self, stmts: &[Statement], inputs: &BTreeSet<String>) -> String {
+        let mut args: Vec<String> = inputs.iter().map(|l| format!("{}: &[f64]", l)).collect();
+        args.push("nocc: usize".to_string());
+        args.push("nvir: usize".to_string());
+
+        let mut s = format!(
+            "pub fn compute_{}({}) -> Vec<f64> {{\n",
+            self.name,
+            args.join(", ")
+        );
+        s.push_str("    let nmo = nocc + nvir;\n");
+        s.push_str(&format!(
+            "    let mut {} = vec![0.0; {}];\n",
+            self.name,
+            len_expr(&self.output)
+        ));
+
+        let mut declared: BTreeSet<String> = BTreeSet::new();
+        for st in stmts {
+            let lhs = &st.lhs;
+            if lhs.label != self.name && declared.insert(lhs.label.clone()) {
+                s.push_str(&format!(
+                    "    let mut {} = vec![0.0; {}];\n",
+                    lhs.label,
+                    len_expr(&lhs.indices)
+                ));
+            }
+
+            // Loop over the lhs indices first, then over the summed ones
+            let mut vars: Vec<&Index> = Vec::new();
+            let all = lhs
+                .indices
+                .iter()
+                .chain(st.tensors.iter().flat_map(|t| &t.indices))
+                .chain(st.deltas.iter().flat_map(|d| [&d.a, &d.b]));
+            for i in all {
+                if !vars.iter().any(|v| v.name() == i.name()) {
+                    vars.push(i);
+                }
+            }
+            let mut indent = String::from("    ");
+            for v in &vars {
+                s.push_str(&format!(
+                    "{}for {} in 0..{} {{\n",
+                    indent,
+                    var_name(v),
+                    dim_name(v.space())
+                ));
+                indent.push_str("    ");
+            }
+
+            let conds: Vec<String> = st
+                .deltas
+                .iter()
+                .map(|d| format!("{} == {}", absolute(&d.a), absolute(&d.b)))
+                .collect();
+            let mut factors: Vec<String> = Vec::new();
+            if st.coeff != 1.0 || st.tensors.is_empty() {
+                factors.push(format!("{:?}", st.coeff));
+            }
+            for t in &st.tensors {
+                factors.push(format!(
+                    "{}[{}]",
+                    t.label,
+                    offset_expr(&t.indices, inputs.contains(&t.label))
+                ));
+            }
+            let update = format!(
+                "{}[{}] += {};\n",
+                lhs.label,
+                offset_expr(&lhs.indices, false),
+                factors.join(" * ")
+            );
+            if conds.is_empty() {
+                s.push_str(&format!("{}{}", indent, update));
+            } else {
+                s.push_str(&format!("{}if {} {{\n", indent, conds.join(" && ")));
+                s.push_str(&format!("{}    {}", indent, update));
+                s.push_str(&format!("{}}}\n", indent));
+            }
+            for _ in &vars {
+                indent.truncate(indent.len() - 4);
+                s.push_str(&format!("{}}}\n", indent));
+            }
+        }
+
+        for (a, b) in self.swapped_axes() {
+            let mut swapped = self.output.clone();
+            swapped.swap(a, b);
+            s.push_str(&format!("    let tmp = {}.clone();\n", self.name));
+            let mut indent = String::from("    ");
+            for v in &self.output {
+                s.push_str(&format!(
+                    "{}for {} in 0..{} {{\n",
+                    indent,
+                    var_name(v),
+                    dim_name(v.space())
+                ));
+                indent.push_str("    ");
+            }
+            s.push_str(&format!(
+                "{}{}[{}] = tmp[{}] - tmp[{}];\n",
+                indent,
+                self.name,
+                offset_expr(&self.output, false),
+                offset_expr(&self.output, false),
+                offset_expr(&swapped, false)
+            ));
+            for _ in &self.output {
+                indent.truncate(indent.len() - 4);
+                s.push_str(&format!("{}}}\n", indent));
+            }
+        }
+        s.push_str(&format!("    {}\n}}\n", self.name));
+        s
+    }
+}
+
+/// Loop variable of an index, only alphanumeric characters and underscores
+fn var_name(i: &Index) -> String {
+    i.name()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '_')
+        .collect()
+}
+
+/// Position of an index in the full orbital range
+fn absolute(i: &Index) -> String {
+    match i.space() {
+        Space::Virtual => format!("(nocc + {})", var_name(i)),
+        _ => var_name(i),
+    }
+}
+
+/// Row-major offset of a tensor element, full range for input tensors
+fn offset_expr(indices: &[Index], full: bool) -> String {
+    let mut acc = String::new();
+    for (k, i) in indices.iter().enumerate() {
+        let (pos, dim) = if full {
+            (absolute(i), "nmo")
+        } else {
+            (var_name(i), dim_name(i.space()))
+        };
+        acc = match k {
+            0 => pos,
+            1 => format!("{} * {} + {}", acc, dim, pos),
+            _ => format!("({}) * {} + {}", acc, dim, pos),
+        };
+    }
+    if acc.is_empty() { "0".to_string() } else { acc }
+}
+
+fn len_expr(indices: &[Index]) -> String {
+    if indices.is_empty() {
+        return "1".to_string();
+    }
+    let dims: Vec<&str> = indices.iter().map(|i| dim_name(i.space())).collect();
+    dims.join(" * ")
+}
+
+// --- C++ tensor expressions ---
+
+impl CodeGen {
+    fn emit_cpp(&self, stmts: &[Statement], intermediates: &BTreeSet<String>) -> String {
+        // Input tensors are passed one block per combination of spaces, f_ov
+        let block = |t: &Tensor| {
+            if intermediates.contains(&t.label) || t.label == self.name {
+                t.label.clone()
+            } else {
+                let spaces: String = t.indices.iter().map(|i| block_letter(i.space())).collect();
+                format!("{}_{}", t.label, spaces)
+            }
+        };
+        let delta_block = |d: &Delta| {
+            format!(
+                "delta_{}{}",
+                block_letter(d.a.space()),
+                block_letter(d.b.space())
+            )
+        };
+        let mut inputs: BTreeSet<String> = BTreeSet::new();
+        for st in stmts {
+            for t in &st.tensors {
+                if !intermediates.contains(&t.label) {
+                    inputs.insert(block(t));
+                }
+            }
+            for d in &st.deltas {
+                inputs.insert(delta_block(d));
+            }
+        }
+
+        let mut args = vec![format!("Tensor& {}", self.name)];
+        args.extend(inputs.iter().map(|b| format!("const Tensor& {}", b)));
+        let mut s = String::from("template <typename Tensor>\n");
+        s.push_str(&format!(
+            "void compute_{}({}) {{\n",
+            self.name,
+            args.join(", ")
+        ));
+        for x in intermediates {
+            s.push_str(&format!("    Tensor {};\n", x));
+        }
+
+        let annotate = |indices: &[Index]| {
+            let names: Vec<String> = indices.iter().map(var_name).collect();
+            format!("(\"{}\")", names.join(","))
+        };
+        for st in stmts {
+            let mut factors: Vec<String> = Vec::new();
+            if st.coeff != 1.0 || (st.tensors.is_empty() && st.deltas.is_empty()) {
+                factors.push(format!("{:?}", st.coeff));
+            }
+            for t in &st.tensors {
+                factors.push(format!("{}{}", block(t), annotate(&t.indices)));
+            }
+            for d in &st.deltas {
+                factors.push(format!("{}{}", delta_block(d), annotate(&[d.a, d.b])));
+            }
+            let op = if st.accumulate { "+=" } else { "=" };
+            s.push_str(&format!(
+                "    {}{} {} {};\n",
+                st.lhs.label,
+                annotate(&st.lhs.indices),
+                op,
+                factors.join(" * ")
+            ));
+        }
+
+        for (a, b) in self.swapped_axes() {
+            let mut swapped = self.output.clone();
+            swapped.swap(a, b);
+            s.push_str("    {\n");
+            s.push_str(&format!("        Tensor tmp = {};\n", self.name));
+            s.push_str(&format!(
+                "        {}{} = tmp{} - tmp{};\n",
+                self.name,
+                annotate(&self.output),
+                annotate(&self.output),
+                annotate(&swapped)
+            ));
+            s.push_str("    }\n");
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+/// Dimension of a space in the generated code
+fn dim_name(space: Space) -> &'static str {
+    match space {
+        Space::Occupied => "nocc",
+        Space::Virtual => "nvir",
+        _ => "nmo",
+    }
+}
+
+/// numpy slice selecting a space from the full orbital range
+fn slice_name(space: Space) -> &'static str {
+    match space {
+        Space::Occupied => "o",
+        Space::Virtual => "v",
+        _ => ":",
+    }
+}
+
+fn block_letter(space: Space) -> char {
+    match space {
+        Space::Occupied => 'o',
+        Space::Virtual => 'v',
+        _ => 'g',
+    }
+}
+
+/// Python tuple literal, a trailing comma for one element
+fn tuple<T: AsRef<str>>(items: &[T]) -> String {
+    let items: Vec<&str> = items.iter().map(|i| i.as_ref()).collect();
+    match items.len() {
+        1 => format!("{},", items[0]),
+        _ => items.join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attr::Vacuum;
+    use crate::factorize::{CostModel, factorize};
+
+    fn idx(name: &str, space: Space) -> Index {
+        Index::new(name)
+            .with_space(space)
+            .with_vacuum(Vacuum::Fermi)
+            .build()
+            .unwrap()
+    }
+
+    /// r_{ia} = f_{ia} + 0.5 g_{ab} t_{ib}
+    fn residual() -> (ResultExpr, Vec<Index>) {
+        let (i, a, b) = (
+            idx("i", Space::Occupied),
+            idx("a", Space::Virtual),
+            idx("b", Space::Virtual),
+        );
+        let expr = 1.0 * Tensor::new("f", vec![i, a])
+            + 0.5 * Tensor::new("g", vec![a, b]) * Tensor::new("t", vec![i, b]);
+        (expr, vec![i, a])
+    }
+
+    #[test]
+    fn test_einsum() {
+        let (expr, output) = residual();
+        let code = CodeGen::new("r", output)
+            .emit(&expr, Target::Einsum)
+            .unwrap();
+        assert!(code.contains("def compute_r(f, g, t, nocc, nvir):"));
+        assert!(code.contains("    r = np.zeros((nocc, nvir))\n"));
+        assert!(code.contains("    r += np.einsum('ia->ia', f[o, v])\n"));
+        assert!(code.contains("    r += 0.5 * np.einsum('ab,ib->ia', g[v, v], t[o, v])\n"));
+    }
+
+    #[test]
+    fn test_rust_loops_factorized() {
+        let (expr, output) = residual();
+        let f = factorize(&expr, &output, &CostModel::new(2, 4));
+        let code = CodeGen::new("r", output)
+            .emit_factorized(&f, Target::Rust)
+            .unwrap();
+        assert!(code.contains("pub fn compute_r(f: &[f64], g: &[f64], t: &[f64], nocc: usize, nvir: usize) -> Vec<f64> {"));
+        assert!(code.contains("    let mut X0 = vec![0.0; nvir * nocc];\n"));
+        assert!(code.contains(
+            "X0[a * nocc + i] += g[(nocc + a) * nmo + (nocc + b)] * t[i * nmo + (nocc + b)];"
+        ));
+        assert!(code.contains("r[i * nvir + a] += 0.5 * X0[a * nocc + i];"));
+    }
+
+    #[test]
+    fn test_cpp_antisymmetrize() {
+        let (i, j, a, b) = (
+            idx("i", Space::Occupied),
+            idx("j", Space::Occupied),
+            idx("a", Space::Virtual),
+            idx("b", Space::Virtual),
+        );
+        let expr = ResultExpr::from_expr(0.5 * Tensor::new("g", vec![i, j, a, b]));
+        let code = CodeGen::new("r", vec![i, j, a, b])
+            .antisymmetrize(i, j)
+            .emit(&expr, Target::Cpp)
+            .unwrap();
+        assert!(code.contains("void compute_r(Tensor& r, const Tensor& g_oovv) {"));
+        assert!(code.contains("    r(\"i,j,a,b\") += 0.5 * g_oovv(\"i,j,a,b\");\n"));
+        assert!(code.contains("        r(\"i,j,a,b\") = tmp(\"i,j,a,b\") - tmp(\"j,i,a,b\");\n"));
+    }
+
+    #[test]
+    fn test_permutation_group() {
+        // Both terms share P(i,j), summed once and antisymmetrized once
+        let expr = crate::parser::parse(
+            "P(i,j) f[k,i] t[a,b,k,j] + 0.5 * P(i,j) g[k,l,i,j] t[a,b,k,l] + g[a,b,i,j]",
+        )
+        .unwrap();
+        let output = crate::parser::parse_term("x[a,b,i,j]").unwrap().tensors[0]
+            .indices
+            .clone();
+        let code = CodeGen::new("r", output)
+            .emit(&expr, Target::Einsum)
+            .unwrap();
+        assert!(!code.contains("import"));
+        assert!(code.contains("def compute_r(f, g, t, nocc, nvir):"));
+        assert!(code.contains("    r_p0 = np.einsum('ki,abkj->abij', f[o, o], t[v, v, o, o])\n"));
+        assert!(code.contains("    r_p0 += 0.5 * np.einsum('klij,abkl->abij',"));
+        assert!(code.contains("    r += np.einsum('abij->abij', r_p0)\n"));
+        assert!(code.contains("    r += -1.0 * np.einsum('abji->abij', r_p0)\n"));
+        assert_eq!(code.matches("r_p0)").count(), 2);
+    }
+}
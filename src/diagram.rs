@@ -0,0 +1,548 @@
+/// src/diagram.rs
+use crate::attr::{Action, Occupancy};
+use crate::expr::Expr;
+use crate::format::latex_index;
+use crate::index::Index;
+use crate::permutation::permutations;
+use crate::wick::{WickStep, WickTheorem};
+
+/// Drawing style of the vertices
+/// Hugenholtz: one point per tensor, antisymmetrized vertices
+/// Goldstone: a tensor of rank 2n is split into n points joined by a dashed
+/// interaction line, point k carrying the k-th creator and annihilator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Style {
+    Hugenholtz,
+    Goldstone,
+}
+
+/// Orientation of a line, from the space of its index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LineKind {
+    Particle,
+    Hole,
+    General,
+}
+
+/// A tensor of the term, or the open end of an operator carried by no tensor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vertex {
+    pub label: String,
+    pub rank: usize,
+    pub external: bool,
+}
+
+/// Contraction line, oriented from the creator to the annihilator.
+/// Ends are (vertex, Goldstone point) pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub kind: LineKind,
+    pub index: Index,
+}
+
+/// Diagram of one fully contracted term: tensors are vertices and every
+/// contraction is a line between the vertices carrying its two operators.
+/// Vertices are ordered like their first operator, the leftmost is the latest.
+#[derive(Debug, Clone)]
+pub struct Diagram {
+    pub vertices: Vec<Vertex>,
+    pub lines: Vec<Line>,
+    pub term: Expr,
+}
+
+impl Diagram {
+    /// Diagram of the contraction `step` of the operator string `expr`
+    pub fn from_step(expr: &Expr, step: &WickStep) -> Self {
+        // Vertex and Goldstone point of every operator
+        let mut order: Vec<(usize, usize)> = Vec::new();
+        let mut open: Vec<usize> = Vec::new();
+        let ends: Vec<(usize, usize)> = slots(expr)
+            .into_iter()
+            .enumerate()
+            .map(|(k, slot)| {
+                let found = slot.map(|(t, pos)| {
+                    let rank = expr.tensors[t].rank();
+                    let half = rank / 2;
+                    let point = if half > 0 && rank.is_multiple_of(2) {
+                        pos % half
+                    } else {
+                        pos
+                    };
+                    (t, point)
+                });
+                match found {
+                    Some(end) => end,
+                    None => {
+                        open.push(k);
+                        (expr.tensors.len() + open.len() - 1, 0)
+                    }
+                }
+            })
+            .collect();
+        for &(v, _) in &ends {
+            if !order.iter().any(|&(w, _)| w == v) {
+                order.push((v, order.len()));
+            }
+        }
+        let renumber = |v: usize| order.iter().find(|&&(w, _)| w == v).unwrap().1;
+
+        let mut vertices: Vec<Option<Vertex>> = vec![None; order.len()];
+        for (t, tensor) in expr.tensors.iter().enumerate() {
+            if let Some(&(_, n)) = order.iter().find(|&&(w, _)| w == t) {
+                vertices[n] = Some(Vertex {
+                    label: tensor.label.clone(),
+                    rank: tensor.rank(),
+                    external: false,
+                });
+            }
+        }
+        for (k, &op) in open.iter().enumerate() {
+            vertices[renumber(expr.tensors.len() + k)] = Some(Vertex {
+                label: expr.ops[op].index.name().to_string(),
+                rank: 1,
+                external: true,
+            });
+        }
+
+        let lines = step
+            .pairing
+            .iter()
+            .map(|&(i, j)| {
+                let (c, a) = if expr.ops[i].action == Action::Create {
+                    (i, j)
+                } else {
+                    (j, i)
+                };
+                let end = |k: usize| (renumber(ends[k].0), ends[k].1);
+                let index = expr.ops[c].index;
+                let kind = match index.space().occupancy() {
+                    Some(Occupancy::Particle) => LineKind::Particle,
+                    Some(Occupancy::Hole) => LineKind::Hole,
+                    _ => LineKind::General,
+                };
+                Line {
+                    from: end(c),
+                    to: end(a),
+                    kind,
+                    index,
+                }
+            })
+            .collect();
+
+        Self {
+            vertices: vertices.into_iter().map(|v| v.unwrap()).collect(),
+            lines,
+            term: step.term.clone(),
+        }
+    }
+
+    pub fn hole_lines(&self) -> usize {
+        self.lines
+            .iter()
+            .filter(|l| l.kind == LineKind::Hole)
+            .count()
+    }
+
+    /// Closed fermion loops of the Goldstone form: a line entering a point
+    /// continues on the line leaving that point
+    pub fn loops(&self) -> usize {
+        let next = |k: usize| self.lines.iter().position(|l| l.from == self.lines[k].to);
+        let mut seen = vec![false; self.lines.len()];
+        let mut loops = 0;
+        for start in 0..self.lines.len() {
+            if seen[start] {
+                continue;
+            }
+            let mut k = start;
+            loop {
+                seen[k] = true;
+                match next(k) {
+                    Some(n) if n == start => {
+                        loops += 1;
+                        break;
+                    }
+                    Some(n) if !seen[n] => k = n,
+                    _ => break,
+                }
+            }
+        }
+        loops
+    }
+
+    /// Sign of the Goldstone diagram, (-1)^(holes + loops)
+    pub fn sign(&self) -> f64 {
+        if (self.hole_lines() + self.loops()).is_multiple_of(2) {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    /// Whether the tensor vertices form a single component
+    pub fn is_connected(&self) -> bool {
+        let n = self.vertices.len();
+        let mut parent: Vec<usize> = (0..n).collect();
+        fn find(parent: &mut [usize], v: usize) -> usize {
+            let mut v = v;
+            while parent[v] != v {
+                parent[v] = parent[parent[v]];
+                v = parent[v];
+            }
+            v
+        }
+        for l in &self.lines {
+            let (a, b) = (find(&mut parent, l.from.0), find(&mut parent, l.to.0));
+            parent[a] = b;
+        }
+        let mut roots: Vec<usize> = (0..n)
+            .filter(|&v| !self.vertices[v].external)
+            .map(|v| find(&mut parent, v))
+            .collect();
+        roots.dedup();
+        roots.windows(2).all(|w| w[0] == w[1])
+    }
+
+    /// Key of the Hugenholtz topology: vertex labels and oriented lines by
+    /// kind, minimized over the renumberings of vertices with equal labels
+    pub fn topology(&self) -> String {
+        let n = self.vertices.len();
+        let mut best: Option<String> = None;
+        for perm in label_permutations(&self.vertices) {
+            let mut vertex_keys: Vec<(usize, String)> = (0..n)
+                .map(|v| (perm[v], self.vertices[v].label.clone()))
+                .collect();
+            vertex_keys.sort();
+            let mut lines: Vec<(usize, usize, LineKind)> = self
+                .lines
+                .iter()
+                .map(|l| (perm[l.from.0], perm[l.to.0], l.kind))
+                .collect();
+            lines.sort();
+            let key = format!("{:?}{:?}", vertex_keys, lines);
+            if best.as_ref().is_none_or(|b| key < *b) {
+                best = Some(key);
+            }
+        }
+        best.unwrap_or_default()
+    }
+
+    pub fn is_equivalent(&self, other: &Self) -> bool {
+        self.topology() == other.topology()
+    }
+
+    /// Graphviz digraph, time running upwards
+    pub fn to_dot(&self, style: Style) -> String {
+        let mut s = String::from("digraph diagram {\n  rankdir=BT;\n");
+        for (v, vertex) in self.vertices.iter().enumerate() {
+            if vertex.external {
+                s.push_str(&format!(
+                    "  v{}_0 [shape=none, label=\"{}\"];\n",
+                    v, vertex.label
+                ));
+                continue;
+            }
+            for p in 0..self.points(v, style) {
+                s.push_str(&format!(
+                    "  v{}_{} [shape=point, xlabel=\"{}\"];\n",
+                    v,
+                    p,
+                    if p == 0 { vertex.label.as_str() } else { "" }
+                ));
+            }
+            for p in 1..self.points(v, style) {
+                s.push_str(&format!(
+                    "  v{}_{} -> v{}_{} [dir=none, style=dashed, constraint=false];\n",
+                    v,
+                    p - 1,
+                    v,
+                    p
+                ));
+            }
+        }
+        for l in &self.lines {
+            let color = match l.kind {
+                LineKind::Hole => ", color=blue",
+                _ => "",
+            };
+            s.push_str(&format!(
+                "  v{}_{} -> v{}_{} [label=\"{}\"{}];\n",
+                l.from.0,
+                self.point(l.from, style),
+                l.to.0,
+                self.point(l.to, style),
+                l.index.name(),
+                color
+            ));
+        }
+        s.push('}');
+        s
+    }
+
+    /// TikZ-Feynman picture, vertices stacked with the latest on top
+    pub fn to_tikz(&self, style: Style) -> String {
+        let n = self.vertices.len();
+        let mut s = String::from("\\begin{tikzpicture}\n\\begin{feynman}\n");
+        for (v, vertex) in self.vertices.iter().enumerate() {
+            let y = 1.5 * (n - 1 - v) as f64;
+            if vertex.external {
+                s.push_str(&format!(
+                    "\\vertex (v{}_0) at (0, {}) {{${}$}};\n",
+                    v,
+                    y,
+                    latex_index(&vertex.label)
+                ));
+                continue;
+            }
+            for p in 0..self.points(v, style) {
+                let label = if p == 0 {
+                    format!(", label=left:${}$", vertex.label)
+                } else {
+                    String::new()
+                };
+                s.push_str(&format!(
+                    "\\vertex [dot{}] (v{}_{}) at ({}, {}) {{}};\n",
+                    label,
+                    v,
+                    p,
+                    1.5 * p as f64,
+                    y
+                ));
+            }
+        }
+        s.push_str("\\diagram* {\n");
+        for v in 0..n {
+            for p in 1..self.points(v, style) {
+                s.push_str(&format!(
+                    "  (v{}_{}) -- [scalar] (v{}_{}),\n",
+                    v,
+                    p - 1,
+                    v,
+                    p
+                ));
+            }
+        }
+        let mut drawn: Vec<((usize, usize), (usize, usize))> = Vec::new();
+        for l in &self.lines {
+            let from = (l.from.0, self.point(l.from, style));
+            let to = (l.to.0, self.point(l.to, style));
+            // Lines between the same points bend alternately left and right
+            let twins = drawn
+                .iter()
+                .filter(|&&(a, b)| (a, b) == (from, to) || (a, b) == (to, from))
+                .count();
+            drawn.push((from, to));
+            let bend = if from == to {
+                ", loop, min distance=1cm"
+            } else {
+                match twins % 3 {
+                    0 => ", half left",
+                    1 => ", half right",
+                    _ => "",
+                }
+            };
+            let kind = match l.kind {
+                LineKind::Hole => "anti fermion",
+                _ => "fermion",
+            };
+            // Holes are drawn against time, from the annihilator to the creator
+            let (a, b) = match l.kind {
+                LineKind::Hole => (to, from),
+                _ => (from, to),
+            };
+            s.push_str(&format!(
+                "  (v{}_{}) -- [{}{}, edge label=${}$] (v{}_{}),\n",
+                a.0,
+                a.1,
+                kind,
+                bend,
+                latex_index(l.index.name()),
+                b.0,
+                b.1
+            ));
+        }
+        s.push_str("};\n\\end{feynman}\n\\end{tikzpicture}");
+        s
+    }
+
+    fn points(&self, v: usize, style: Style) -> usize {
+        let vertex = &self.vertices[v];
+        match style {
+            Style::Goldstone if !vertex.external && vertex.rank.is_multiple_of(2) => {
+                (vertex.rank / 2).max(1)
+            }
+            _ => 1,
+        }
+    }
+
+    fn point(&self, end: (usize, usize), style: Style) -> usize {
+        match style {
+            Style::Goldstone => end.1.min(self.points(end.0, style) - 1),
+            Style::Hugenholtz => 0,
+        }
+    }
+}
+
+impl WickTheorem {
+    /// Diagrams of the traced full contractions, empty unless `trace` was on
+    pub fn diagrams(&self) -> Vec<Diagram> {
+        self.steps()
+            .iter()
+            .map(|s| Diagram::from_step(self.expr(), s))
+            .collect()
+    }
+}
+
+/// Group equivalent diagrams, each class as indices into `diagrams`
+pub fn classify(diagrams: &[Diagram]) -> Vec<Vec<usize>> {
+    let mut classes: Vec<(String, Vec<usize>)> = Vec::new();
+    for (k, d) in diagrams.iter().enumerate() {
+        let key = d.topology();
+        match classes.iter_mut().find(|(c, _)| *c == key) {
+            Some((_, members)) => members.push(k),
+            None => classes.push((key, vec![k])),
+        }
+    }
+    classes.into_iter().map(|(_, m)| m).collect()
+}
+
+/// Tensor and index position carrying every operator, found by position:
+/// each tensor takes the first run of operators after the previous run
+/// whose indices are its own, so the operators written after a tensor stay
+/// with it even when a summed index also shows up in another tensor.
+/// Operators left over take the first free slot with their index, or none.
+fn slots(expr: &Expr) -> Vec<Option<(usize, usize)>> {
+    let ops = &expr.ops;
+    let mut slot: Vec<Option<(usize, usize)>> = vec![None; ops.len()];
+    let mut used: Vec<Vec<bool>> = expr.tensors.iter().map(|t| vec![false; t.rank()]).collect();
+    let take = |t: usize, k: usize, used: &mut Vec<Vec<bool>>| {
+        let pos = (0..used[t].len())
+            .find(|&p| !used[t][p] && expr.tensors[t].indices[p] == ops[k].index)?;
+        used[t][pos] = true;
+        Some((t, pos))
+    };
+    let sorted = |mut names: Vec<&'static str>| {
+        names.sort_unstable();
+        names
+    };
+
+    let mut cursor = 0;
+    for (t, tensor) in expr.tensors.iter().enumerate() {
+        let n = tensor.rank();
+        if n == 0 || cursor + n > ops.len() {
+            continue;
+        }
+        let want = sorted(tensor.indices.iter().map(|i| i.name()).collect());
+        let run = (cursor..=ops.len() - n).find(|&c| {
+            slot[c..c + n].iter().all(|s| s.is_none())
+                && sorted(ops[c..c + n].iter().map(|o| o.index.name()).collect()) == want
+        });
+        if let Some(c) = run {
+            for (k, s) in slot.iter_mut().enumerate().skip(c).take(n) {
+                *s = take(t, k, &mut used);
+            }
+            cursor = c + n;
+        }
+    }
+    for (k, s) in slot.iter_mut().enumerate() {
+        if s.is_none() {
+            *s = (0..expr.tensors.len()).find_map(|t| take(t, k, &mut used));
+        }
+    }
+    slot
+}
+
+/// Renumberings of the vertices that only permute equal labels
+fn label_permutations(vertices: &[Vertex]) -> Vec<Vec<usize>> {
+    let mut res = vec![vec![usize::MAX; vertices.len()]];
+    let mut done = vec![false; vertices.len()];
+    for v in 0..vertices.len() {
+        if done[v] {
+            continue;
+        }
+        let group: Vec<usize> = (v..vertices.len())
+            .filter(|&w| {
+                vertices[w].label == vertices[v].label
+                    && vertices[w].external == vertices[v].external
+            })
+            .collect();
+        for &w in &group {
+            done[w] = true;
+        }
+        let mut next = Vec::new();
+        for perm in &res {
+            for order in permutations(&group) {
+                let mut p = perm.clone();
+                for (slot, &w) in group.iter().zip(&order) {
+                    p[*slot] = w;
+                }
+                next.push(p);
+            }
+        }
+        res = next;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_term;
+
+    fn doubles_energy() -> WickTheorem {
+        let e =
+            parse_term("1/16 g[m,n,e,f] a+[m] a+[n] a[f] a[e] t[c,d,k,l] a+[c] a+[d] a[l] a[k]")
+                .unwrap();
+        let vacuum = e.vacuum().unwrap();
+        let mut wt = WickTheorem::new(e, vacuum);
+        wt.full_contractions(true).trace(true).compute().unwrap();
+        wt
+    }
+
+    #[test]
+    fn test_goldstone_sign() {
+        let wt = doubles_energy();
+        let diagrams = wt.diagrams();
+        assert_eq!(diagrams.len(), 4);
+        for (d, step) in diagrams.iter().zip(wt.steps()) {
+            assert!(d.is_connected());
+            assert_eq!(d.hole_lines(), 2);
+            assert_eq!(d.sign(), step.sign);
+        }
+        assert_eq!(classify(&diagrams), vec![vec![0, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn test_vertex_by_position() {
+        // m and e are summed over u and g, the operators belong to g
+        let e =
+            parse_term("u[m,e] g[m,n,e,f] a+[m] a+[n] a[f] a[e] t[c,d,k,l] a+[c] a+[d] a[l] a[k]")
+                .unwrap();
+        let vacuum = e.vacuum().unwrap();
+        let mut wt = WickTheorem::new(e, vacuum);
+        wt.full_contractions(true).trace(true).compute().unwrap();
+        for d in wt.diagrams() {
+            let labels: Vec<&str> = d.vertices.iter().map(|v| v.label.as_str()).collect();
+            assert_eq!(labels, ["g", "t"]);
+            assert!(d.is_connected());
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let d = &doubles_energy().diagrams()[0];
+        let dot = d.to_dot(Style::Hugenholtz);
+        assert!(
+            dot.starts_with(
+                "digraph diagram {\n  rankdir=BT;\n  v0_0 [shape=point, xlabel=\"g\"];"
+            )
+        );
+        assert!(dot.contains("v0_0 -> v1_0 [label=\"m\", color=blue];"));
+        let dot = d.to_dot(Style::Goldstone);
+        assert!(dot.contains("v0_0 -> v0_1 [dir=none, style=dashed, constraint=false];"));
+        let tikz = d.to_tikz(Style::Goldstone);
+        assert!(tikz.contains("\\vertex [dot, label=left:$t$] (v1_0) at (0, 0) {};"));
+        assert!(tikz.contains("(v0_0) -- [scalar] (v0_1),"));
+    }
+}
@@ -0,0 +1,220 @@
+/// src/error.rs
+use crate::attr::{Space, Statistics, Vacuum};
+use crate::index::Index;
+use crate::parser::ParseError;
+use std::fmt;
+
+/// Errors of the library, every fallible operation reports one of these
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// An index space the vacuum does not know, General on the Fermi vacuum
+    IllegalSpace { space: Space, vacuum: Vacuum },
+    /// A space definition the registry refuses, e.g. a taken name
+    InvalidSpace { name: String, reason: &'static str },
+    /// A relabeling of an index the term lacks, or one sending two indices
+    /// of the term to the same index
+    InvalidRelabeling { index: Index, reason: &'static str },
+    /// Operators, by position, whose index is on another vacuum than the
+    /// expression or in a space that vacuum does not allow
+    MixedVacua {
+        vacuum: Vacuum,
+        positions: Vec<usize>,
+    },
+    /// Fermionic and bosonic operators in the same product
+    MismatchedStatistics { left: Statistics, right: Statistics },
+    /// An operation that has no meaning on the vacuum, e.g. Wick on MR
+    UnsupportedVacuum {
+        vacuum: Vacuum,
+        operation: &'static str,
+    },
+    /// An operation defined for one statistics only, e.g. Weyl ordering
+    /// of fermions
+    UnsupportedStatistics {
+        statistic: Statistics,
+        operation: &'static str,
+    },
+    /// A term of a sum whose free indices differ from the first term
+    UnbalancedIndices {
+        term: usize,
+        expected: Vec<Index>,
+        found: Vec<Index>,
+    },
+    /// The same index name with two different spaces in one term
+    ConflictingSpaces {
+        name: &'static str,
+        first: Space,
+        second: Space,
+    },
+    /// An index space a numerical backend has no dimension or slice for
+    UnsupportedSpace {
+        space: Space,
+        operation: &'static str,
+    },
+    /// A term with operators where only tensors and deltas can be handled,
+    /// e.g. when evaluating or generating code
+    UncontractedOperators {
+        term: String,
+        operation: &'static str,
+    },
+    /// A tensor label without a bound array
+    UnboundTensor { label: String },
+    /// Data whose element count does not fit the shape
+    ShapeMismatch { shape: Vec<usize>, len: usize },
+    /// A bound array of the wrong rank for its tensor
+    RankMismatch {
+        label: String,
+        expected: usize,
+        found: usize,
+    },
+    /// An array axis whose length fits none of the allowed dimensions
+    DimensionMismatch {
+        label: String,
+        axis: usize,
+        expected: Vec<usize>,
+        found: usize,
+    },
+    /// More distinct indices than a backend has names for
+    TooManyIndices { count: usize, limit: usize },
+    /// An index that has to be an output index of the generated tensor
+    NotAnOutput { index: Index },
+    /// An archive written by a newer format than this crate reads
+    UnsupportedFormat { version: u32, supported: u32 },
+    /// Serializing or deserializing failed, with the message of serde
+    Serialization(String),
+    /// A named operator placeholder where explicit operators are needed
+    UnexpandedPlaceholder { name: String },
+    /// Text that does not follow the expression syntax, with the offset
+    Parse(ParseError),
+    /// A session statement that cannot run, e.g. an unknown name or space
+    Repl { message: String },
+    /// A failing line of a session script, counted from 1
+    Script { line: usize, error: Box<Error> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IllegalSpace { space, vacuum } => {
+                write!(f, "Illegal Space {:?} for Vacuum {:?}", space, vacuum)
+            }
+            Self::InvalidSpace { name, reason } => {
+                write!(f, "Cannot define space {}: {}", name, reason)
+            }
+            Self::InvalidRelabeling { index, reason } => {
+                write!(f, "Cannot relabel {}: {}", index.name(), reason)
+            }
+            Self::MixedVacua { vacuum, positions } => {
+                write!(
+                    f,
+                    "Operators at {:?} do not belong to {}",
+                    positions, vacuum
+                )
+            }
+            Self::MismatchedStatistics { left, right } => {
+                write!(f, "Mismatched statistics {:?} and {:?}", left, right)
+            }
+            Self::UnsupportedVacuum { vacuum, operation } => {
+                write!(f, "{} is not supported for {}", operation, vacuum)
+            }
+            Self::UnsupportedStatistics {
+                statistic,
+                operation,
+            } => {
+                write!(f, "{} is not supported for {:?}", operation, statistic)
+            }
+            Self::UnbalancedIndices {
+                term,
+                expected,
+                found,
+            } => {
+                let names = |v: &[Index]| v.iter().map(|i| i.name()).collect::<Vec<_>>();
+                write!(
+                    f,
+                    "Term {} has free indices {:?}, expected {:?}",
+                    term,
+                    names(found),
+                    names(expected)
+                )
+            }
+            Self::UnexpandedPlaceholder { name } => {
+                write!(f, "Placeholder {} has to be substituted first", name)
+            }
+            Self::ConflictingSpaces {
+                name,
+                first,
+                second,
+            } => {
+                write!(
+                    f,
+                    "Index {} used with spaces {} and {}",
+                    name, first, second
+                )
+            }
+            Self::UnsupportedSpace { space, operation } => {
+                write!(f, "{} does not support {}", operation, space)
+            }
+            Self::UncontractedOperators { term, operation } => {
+                write!(f, "{} needs a term without operators: {}", operation, term)
+            }
+            Self::UnboundTensor { label } => write!(f, "No tensor bound to label {}", label),
+            Self::ShapeMismatch { shape, len } => {
+                let needed: usize = shape.iter().product();
+                write!(
+                    f,
+                    "Shape {:?} needs {} elements, got {}",
+                    shape, needed, len
+                )
+            }
+            Self::RankMismatch {
+                label,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Tensor {} has rank {}, bound array has rank {}",
+                label, expected, found
+            ),
+            Self::DimensionMismatch {
+                label,
+                axis,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Axis {} of tensor {} has length {}, expected one of {:?}",
+                axis, label, found, expected
+            ),
+            Self::TooManyIndices { count, limit } => {
+                write!(f, "{} indices, at most {} are supported", count, limit)
+            }
+            Self::NotAnOutput { index } => {
+                write!(f, "Index {} is not an output index", index.name())
+            }
+            Self::UnsupportedFormat { version, supported } => write!(
+                f,
+                "Archive format version {} is newer than supported version {}",
+                version, supported
+            ),
+            Self::Serialization(e) => write!(f, "Serialization failed: {}", e),
+            Self::Parse(e) => write!(f, "{}", e),
+            Self::Repl { message } => write!(f, "{}", message),
+            Self::Script { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parse(e) => Some(e),
+            Self::Script { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Self::Parse(e)
+    }
+}
@@ -0,0 +1,381 @@
+/// src/eval.rs
+use crate::attr::Space;
+use crate::error::Error;
+use crate::expr::Expr;
+use crate::factorize::Factorization;
+use crate::index::Index;
+use crate::result_expr::ResultExpr;
+use std::collections::HashMap;
+
+/// DenseTensor is a row-major n-dimensional array of f64.
+/// A rank-0 tensor (empty shape) holds a single scalar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DenseTensor {
+    pub shape: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+impl DenseTensor {
+    pub fn zeros(shape: Vec<usize>) -> Self {
+        let len = shape.iter().product();
+        Self {
+            shape,
+            data: vec![0.0; len],
+        }
+    }
+
+    pub fn from_vec(shape: Vec<usize>, data: Vec<f64>) -> Result<Self, Error> {
+        let len: usize = shape.iter().product();
+        if len != data.len() {
+            return Err(Error::ShapeMismatch {
+                shape,
+                len: data.len(),
+            });
+        }
+        Ok(Self { shape, data })
+    }
+
+    pub fn scalar(value: f64) -> Self {
+        Self {
+            shape: Vec::new(),
+            data: vec![value],
+        }
+    }
+
+    /// Row-major strides of the tensor
+    pub fn strides(&self) -> Vec<usize> {
+        let mut strides = vec![1; self.shape.len()];
+        for k in (0..self.shape.len().saturating_sub(1)).rev() {
+            strides[k] = strides[k + 1] * self.shape[k + 1];
+        }
+        strides
+    }
+
+    fn offset(&self, idx: &[usize]) -> usize {
+        assert_eq!(idx.len(), self.shape.len(), "Wrong number of indices");
+        idx.iter().zip(&self.shape).fold(0, |acc, (&i, &n)| {
+            assert!(i < n, "Index {} out of range {}", i, n);
+            acc * n + i
+        })
+    }
+
+    pub fn get(&self, idx: &[usize]) -> f64 {
+        self.data[self.offset(idx)]
+    }
+
+    pub fn set(&mut self, idx: &[usize], value: f64) {
+        let k = self.offset(idx);
+        self.data[k] = value;
+    }
+
+    /// Some interface
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+    pub fn data(&self) -> &[f64] {
+        &self.data
+    }
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+}
+
+/// Evaluator binds tensor labels to dense arrays and evaluates a
+/// fully contracted ResultExpr numerically.
+/// Occupied indices run over 0..nocc, Virtual over 0..nvir and
+/// General over the full orbital range 0..nocc+nvir (occupied first).
+/// A bound array axis may either span the full orbital range, in which case
+/// it is sliced according to the index space, or exactly the index space.
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    nocc: usize,
+    nvir: usize,
+    tensors: HashMap<String, DenseTensor>,
+}
+
+impl Evaluator {
+    pub fn new(nocc: usize, nvir: usize) -> Self {
+        Self {
+            nocc,
+            nvir,
+            tensors: HashMap::new(),
+        }
+    }
+
+    pub fn bind(mut self, label: impl Into<String>, tensor: DenseTensor) -> Self {
+        self.tensors.insert(label.into(), tensor);
+        self
+    }
+
+    /// Dimension of an orbital space
+    pub fn dim(&self, space: Space) -> Result<usize, Error> {
+        match space {
+            Space::Occupied => Ok(self.nocc),
+            Space::Virtual => Ok(self.nvir),
+            Space::General => Ok(self.nocc + self.nvir),
+            _ => Err(Error::UnsupportedSpace {
+                space,
+                operation: "Evaluation",
+            }),
+        }
+    }
+
+    /// Position of the first orbital of a space in the full orbital range
+    fn offset(&self, space: Space) -> usize {
+        match space {
+            Space::Virtual => self.nocc,
+            _ => 0,
+        }
+    }
+
+    /// Evaluate every term of the expression and sum into a tensor
+    /// whose axes follow `output`. All other indices are summed over,
+    /// permutation operators are expanded first.
+    pub fn evaluate(&self, expr: &ResultExpr, output: &[Index]) -> Result<DenseTensor, Error> {
+        let shape = output
+            .iter()
+            .map(|i| self.dim(i.space()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut result = DenseTensor::zeros(shape);
+        for term in expr.expand_permutations().terms() {
+            self.accumulate(term, output, &mut result)?;
+        }
+        Ok(result)
+    }
+
+    /// Evaluate the intermediates of a factorization in order, then its terms
+    pub fn evaluate_factorized(
+        &self,
+        f: &Factorization,
+        output: &[Index],
+    ) -> Result<DenseTensor, Error> {
+        let mut ev = self.clone();
+        for x in f.intermediates() {
+            let pair = 1.0 * x.left.clone() * x.right.clone();
+            let value = ev.evaluate(&ResultExpr::from_expr(pair), x.tensor.indices())?;
+            ev.tensors.insert(x.tensor.label.clone(), value);
+        }
+        let terms = ResultExpr::from_terms(f.terms().to_vec());
+        ev.evaluate(&terms, output)
+    }
+
+    fn accumulate(
+        &self,
+        term: &Expr,
+        output: &[Index],
+        out: &mut DenseTensor,
+    ) -> Result<(), Error> {
+        if let Some(p) = term.placeholders().first() {
+            return Err(Error::UnexpandedPlaceholder {
+                name: p.name.clone(),
+            });
+        }
+        if !term.ops().is_empty() {
+            return Err(Error::UncontractedOperators {
+                term: term.to_latex(),
+                operation: "Evaluation",
+            });
+        }
+
+        // Loop variables: output indices first, then every summed index
+        let mut vars: Vec<&Index> = output.iter().collect();
+        for t in term.tensors() {
+            for idx in t.indices() {
+                push_var(&mut vars, idx)?;
+            }
+        }
+        for d in term.deltas() {
+            push_var(&mut vars, &d.a)?;
+            push_var(&mut vars, &d.b)?;
+        }
+        let position = |idx: &Index| vars.iter().position(|v| v.name() == idx.name()).unwrap();
+
+        let dims = vars
+            .iter()
+            .map(|v| self.dim(v.space()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let offsets: Vec<usize> = vars.iter().map(|v| self.offset(v.space())).collect();
+        let full = self.nocc + self.nvir;
+
+        // For every tensor axis: (loop variable, uses full orbital range, stride)
+        let mut factors = Vec::with_capacity(term.tensors().len());
+        for t in term.tensors() {
+            let dense = self
+                .tensors
+                .get(t.label())
+                .ok_or_else(|| Error::UnboundTensor {
+                    label: t.label().to_string(),
+                })?;
+            if dense.ndim() != t.rank() {
+                return Err(Error::RankMismatch {
+                    label: t.label().to_string(),
+                    expected: t.rank(),
+                    found: dense.ndim(),
+                });
+            }
+            let strides = dense.strides();
+            let mut axes = Vec::with_capacity(t.rank());
+            for (k, idx) in t.indices().iter().enumerate() {
+                let v = position(idx);
+                let n = dense.shape[k];
+                let use_full = if n == dims[v] {
+                    false
+                } else if n == full {
+                    true
+                } else {
+                    return Err(Error::DimensionMismatch {
+                        label: t.label().to_string(),
+                        axis: k,
+                        expected: vec![dims[v], full],
+                        found: n,
+                    });
+                };
+                axes.push((v, use_full, strides[k]));
+            }
+            factors.push((dense, axes));
+        }
+        let deltas: Vec<(usize, usize)> = term
+            .deltas()
+            .iter()
+            .map(|d| (position(&d.a), position(&d.b)))
+            .collect();
+
+        if dims.contains(&0) {
+            return Ok(());
+        }
+
+        // Odometer over every loop variable
+        let out_strides = out.strides();
+        let mut counter = vec![0usize; vars.len()];
+        loop {
+            let same_orbital = deltas
+                .iter()
+                .all(|&(a, b)| offsets[a] + counter[a] == offsets[b] + counter[b]);
+            if same_orbital {
+                let mut value = term.coeff();
+                for (dense, axes) in &factors {
+                    let k: usize = axes
+                        .iter()
+                        .map(|&(v, use_full, stride)| {
+                            let i = if use_full {
+                                offsets[v] + counter[v]
+                            } else {
+                                counter[v]
+                            };
+                            i * stride
+                        })
+                        .sum();
+                    value *= dense.data[k];
+                }
+                let k: usize = (0..output.len()).map(|v| counter[v] * out_strides[v]).sum();
+                out.data[k] += value;
+            }
+
+            // Advance the odometer, last variable fastest
+            let mut v = vars.len();
+            loop {
+                if v == 0 {
+                    return Ok(());
+                }
+                v -= 1;
+                counter[v] += 1;
+                if counter[v] < dims[v] {
+                    break;
+                }
+                counter[v] = 0;
+            }
+        }
+    }
+}
+
+/// Register a loop variable once, an index name must keep a single space
+fn push_var<'a>(vars: &mut Vec<&'a Index>, idx: &'a Index) -> Result<(), Error> {
+    match vars.iter().find(|v| v.name() == idx.name()) {
+        Some(v) if v.space() != idx.space() => Err(Error::ConflictingSpaces {
+            name: idx.name(),
+            first: v.space(),
+            second: idx.space(),
+        }),
+        Some(_) => Ok(()),
+        None => {
+            vars.push(idx);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attr::Vacuum;
+    use crate::tensor::Tensor;
+
+    fn occ(name: &str) -> Index {
+        Index::new(name)
+            .with_space(Space::Occupied)
+            .with_vacuum(Vacuum::Fermi)
+            .build()
+            .unwrap()
+    }
+
+    fn vir(name: &str) -> Index {
+        Index::new(name)
+            .with_space(Space::Virtual)
+            .with_vacuum(Vacuum::Fermi)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_matrix_product() {
+        // C_{ij} = 2 * A_{ia} B_{ja}
+        let (i, j, a) = (occ("i"), occ("j"), vir("a"));
+        let a_mat = DenseTensor::from_vec(vec![2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        let b_mat = DenseTensor::from_vec(vec![2, 3], vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0]).unwrap();
+        let expr = 2.0 * Tensor::new("A", vec![i, a]) * Tensor::new("B", vec![j, a]);
+
+        let c = Evaluator::new(2, 3)
+            .bind("A", a_mat)
+            .bind("B", b_mat)
+            .evaluate(&ResultExpr::from_expr(expr), &[i, j])
+            .unwrap();
+        assert_eq!(c.shape(), &[2, 2]);
+        assert_eq!(c.data(), &[8.0, 4.0, 20.0, 10.0]);
+    }
+
+    #[test]
+    fn test_full_range_slicing_and_delta() {
+        // E = f_{ii} + f_{ab} delta_{ab}, with f bound over all 3 orbitals
+        let (i, a, b) = (occ("i"), vir("a"), vir("b"));
+        let f = DenseTensor::from_vec(
+            vec![3, 3],
+            vec![1.0, 9.0, 9.0, 9.0, 2.0, 9.0, 9.0, 9.0, 3.0],
+        )
+        .unwrap();
+        let mut e2 = 1.0 * Tensor::new("f", vec![a, b]);
+        e2.add_delta(crate::op::Delta { a, b });
+        let expr = 1.0 * Tensor::new("f", vec![i, i]) + e2;
+
+        let e = Evaluator::new(1, 2)
+            .bind("f", f)
+            .evaluate(&expr, &[])
+            .unwrap();
+        assert_eq!(e.data(), &[6.0]);
+    }
+
+    #[test]
+    fn test_unbound_tensor() {
+        let i = occ("i");
+        let expr = ResultExpr::from_expr(1.0 * Tensor::new("t", vec![i]));
+        assert_eq!(
+            Evaluator::new(1, 1).evaluate(&expr, &[i]),
+            Err(Error::UnboundTensor {
+                label: "t".to_string()
+            })
+        );
+        assert!(matches!(
+            DenseTensor::from_vec(vec![2, 2], vec![1.0]),
+            Err(Error::ShapeMismatch { len: 1, .. })
+        ));
+    }
+}
@@ -1,13 +1,57 @@
-use crate::attr::{Action, Statistics};
-use crate::op::{Delta, Op, can_contract};
+use crate::attr::{Action, Statistics, Vacuum};
+use crate::error::Error;
+use crate::format::Format;
+use crate::index::{Index, IndexFactory};
+use crate::op::{Delta, Op, is_out_of_order, is_quasi_annihilator};
+use crate::permutation::Permutation;
+use crate::placeholder::Placeholder;
+use crate::tensor::Tensor;
+use std::collections::HashMap;
 use std::ops::Mul;
 
+/// Canonical form of a term without its coefficient: the ops in order,
+/// the deltas and the tensors as sorted multisets. Indices are compared
+/// with their space and vacuum everywhere, like in `ops`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TermKey {
+    statistic: Statistics,
+    ops: Vec<Op>,
+    deltas: Vec<(Index, Index)>,
+    tensors: Vec<(String, Vec<Index>)>,
+    perms: Vec<Vec<(&'static str, &'static str)>>,
+    placeholders: Vec<Placeholder>,
+}
+
+/// Which indices of a term are summed over
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Summation {
+    /// Indices with more than one slot, the rest are free
+    #[default]
+    Einstein,
+    /// Exactly the listed indices, wherever they appear
+    Explicit(Vec<Index>),
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Expr {
     pub coeff: f64,
     pub deltas: Vec<Delta>,
+    pub tensors: Vec<Tensor>,
     pub ops: Vec<Op>,
     pub statistic: Statistics,
+    /// Permutation operators acting on the whole term
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub perms: Vec<Permutation>,
+    /// Named operators in the operator string, see `Placeholder`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub placeholders: Vec<Placeholder>,
+}
+
+impl Default for Expr {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Expr {
@@ -15,14 +59,21 @@ impl Expr {
         Self {
             coeff: 1.0,
             deltas: Vec::new(),
+            tensors: Vec::new(),
             ops: Vec::new(),
             statistic: Statistics::FermiDirac,
+            perms: Vec::new(),
+            placeholders: Vec::new(),
         }
     }
     fn with_op(mut self, op: Op) -> Self {
         self.ops.push(op);
         self
     }
+    fn with_tensor(mut self, tensor: Tensor) -> Self {
+        self.tensors.push(tensor);
+        self
+    }
 
     pub fn set_coeff(mut self, coeff: f64) -> Self {
         self.coeff = coeff;
@@ -36,9 +87,20 @@ impl Expr {
     pub fn append_op(&mut self, op: Op) {
         self.ops.push(op);
     }
+    pub fn append_tensor(&mut self, tensor: Tensor) {
+        self.tensors.push(tensor);
+    }
     pub fn append_expr(&mut self, other: &Self) {
+        let shift = self.ops.len();
+        self.placeholders
+            .extend(other.placeholders.iter().map(|p| Placeholder {
+                position: p.position + shift,
+                ..p.clone()
+            }));
         self.ops.extend(other.ops.iter().cloned());
         self.deltas.extend(other.deltas.iter().cloned());
+        self.tensors.extend(other.tensors.iter().cloned());
+        self.perms.extend(other.perms.iter().cloned());
         self.coeff *= other.coeff;
     }
 
@@ -48,13 +110,156 @@ impl Expr {
         }
         for d in &mut self.deltas {
             if d.a == delta.b {
-                d.a = delta.a.clone();
+                d.a = delta.a;
                 return;
             }
         }
         self.deltas.push(delta);
     }
 
+    /// Every index slot: tensors, deltas, then operators
+    pub fn indices(&self) -> impl Iterator<Item = Index> + '_ {
+        self.tensors
+            .iter()
+            .flat_map(|t| t.indices.iter().copied())
+            .chain(self.deltas.iter().flat_map(|d| [d.a, d.b]))
+            .chain(self.ops.iter().map(|o| o.index))
+    }
+
+    /// Vacuum shared by every index of the term, Physical for a term without
+    /// indices. Mixed vacua fail with the operators off the first index's.
+    pub fn vacuum(&self) -> Result<Vacuum, Error> {
+        let Some(vacuum) = self.indices().next().map(|i| i.vacuum()) else {
+            return Ok(Vacuum::Physical);
+        };
+        if self.indices().all(|i| i.vacuum() == vacuum) {
+            return Ok(vacuum);
+        }
+        Err(Error::MixedVacua {
+            vacuum,
+            positions: (0..self.ops.len())
+                .filter(|&k| self.ops[k].vacuum() != vacuum)
+                .collect(),
+        })
+    }
+
+    /// Distinct indices with their slot count, in order of appearance
+    fn slot_counts(&self) -> Vec<(Index, usize)> {
+        let mut seen: Vec<(Index, usize)> = Vec::new();
+        for i in self.indices() {
+            match seen.iter_mut().find(|(j, _)| *j == i) {
+                Some((_, n)) => *n += 1,
+                None => seen.push((i, 1)),
+            }
+        }
+        seen
+    }
+
+    /// Indices with more than one slot, summed over, in order of appearance
+    pub fn dummy_indices(&self) -> Vec<Index> {
+        self.summed_indices(&Summation::Einstein)
+    }
+
+    pub fn summed_indices(&self, summation: &Summation) -> Vec<Index> {
+        self.classify(summation, true)
+    }
+
+    /// External indices of the term, in order of appearance
+    pub fn free_indices(&self, summation: &Summation) -> Vec<Index> {
+        self.classify(summation, false)
+    }
+
+    fn classify(&self, summation: &Summation, summed: bool) -> Vec<Index> {
+        self.slot_counts()
+            .into_iter()
+            .filter(|(i, n)| {
+                let is_summed = match summation {
+                    Summation::Einstein => *n > 1,
+                    Summation::Explicit(list) => list.contains(i),
+                };
+                is_summed == summed
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Replace every slot of `from` by `to`
+    pub(crate) fn rename(&mut self, from: Index, to: Index) {
+        self.map_indices(|i| if i == from { to } else { i });
+    }
+
+    /// Apply `f` to every slot, deltas that become trivial are dropped
+    fn map_indices(&mut self, f: impl Fn(Index) -> Index) {
+        for i in self.tensors.iter_mut().flat_map(|t| t.indices.iter_mut()) {
+            *i = f(*i);
+        }
+        for o in &mut self.ops {
+            o.index = f(o.index);
+        }
+        for d in &mut self.deltas {
+            (d.a, d.b) = (f(d.a), f(d.b));
+        }
+        self.deltas.retain(|d| d.a != d.b);
+        for (a, b) in self.perms.iter_mut().flat_map(|p| p.pairs.iter_mut()) {
+            (*a, *b) = (f(*a), f(*b));
+        }
+    }
+
+    /// The term with `a` and `b` exchanged everywhere
+    pub(crate) fn swapped(&self, a: Index, b: Index) -> Expr {
+        let mut e = self.clone();
+        e.map_indices(|i| match i {
+            i if i == a => b,
+            i if i == b => a,
+            i => i,
+        });
+        e
+    }
+
+    /// Rename indices all at once, so {i: j, j: i} swaps them.
+    /// Every key must be an index of the term, every new index must pass
+    /// `Index::build`, and no two indices of the term may end up the same.
+    pub fn relabel(&self, map: &HashMap<Index, Index>) -> Result<Expr, Error> {
+        let indices: Vec<Index> = self.slot_counts().into_iter().map(|(i, _)| i).collect();
+        if let Some(&from) = map.keys().find(|k| !indices.contains(k)) {
+            return Err(Error::InvalidRelabeling {
+                index: from,
+                reason: "it is not an index of the term",
+            });
+        }
+        let mut images: Vec<Index> = Vec::with_capacity(indices.len());
+        for i in indices {
+            let to = map.get(&i).copied().unwrap_or(i);
+            if images.contains(&to) {
+                return Err(Error::InvalidRelabeling {
+                    index: i,
+                    reason: "another index of the term is mapped to the same index",
+                });
+            }
+            images.push(to);
+        }
+        for to in map.values() {
+            to.build()?;
+        }
+        let mut e = self.clone();
+        e.map_indices(|i| map.get(&i).copied().unwrap_or(i));
+        Ok(e)
+    }
+
+    /// Put `to` in place of `from`, both restricted to the common space,
+    /// e.g. p -> i turns every p into i and f[p,q] into f[i,q].
+    /// None when the spaces are disjoint and the term vanishes.
+    pub fn substitute_index(&self, from: Index, to: Index) -> Result<Option<Expr>, Error> {
+        let Some(space) = from.space().intersection(to.space())? else {
+            return Ok(None);
+        };
+        // Merging `from` and `to` is the point here, so no injectivity check
+        let target = to.with_space(space).build()?;
+        let mut e = self.clone();
+        e.map_indices(|i| if i == from || i == to { target } else { i });
+        Ok(Some(e))
+    }
+
     /// Some interface
     pub fn coeff(&self) -> f64 {
         self.coeff
@@ -67,40 +272,182 @@ impl Expr {
     pub fn deltas(&self) -> &[Delta] {
         &self.deltas
     }
+    /// Some interface
+    pub fn tensors(&self) -> &[Tensor] {
+        &self.tensors
+    }
+    /// Some interface
+    pub fn placeholders(&self) -> &[Placeholder] {
+        &self.placeholders
+    }
     pub fn is_fermi(&self) -> bool {
-        match self.statistic {
-            Statistics::FermiDirac => true,
-            _ => false,
-        }
+        matches!(self.statistic, Statistics::FermiDirac)
     }
     pub fn is_bose(&self) -> bool {
-        match self.statistic {
-            Statistics::BoseEinstein => true,
-            _ => false,
+        matches!(self.statistic, Statistics::BoseEinstein)
+    }
+
+    /// Terms with equal keys only differ by their coefficient
+    pub fn key(&self) -> TermKey {
+        let mut deltas: Vec<(Index, Index)> = self.deltas.iter().map(|d| d.canonical()).collect();
+        deltas.sort();
+        // Tensors commute, compare them as a multiset
+        let mut tensors: Vec<(String, Vec<Index>)> = self
+            .tensors
+            .iter()
+            .map(|t| {
+                let (label, idx) = t.sort_key();
+                (label.to_string(), idx)
+            })
+            .collect();
+        tensors.sort();
+        let mut perms: Vec<_> = self.perms.iter().map(|p| p.sort_key()).collect();
+        perms.sort();
+        TermKey {
+            statistic: self.statistic,
+            ops: self.ops.clone(),
+            deltas,
+            tensors,
+            perms,
+            placeholders: self.placeholders.clone(),
         }
     }
 
     pub fn is_similar(&self, other: &Self) -> bool {
-        if self.statistic != other.statistic {
-            return false;
-        }
-        if self.ops != other.ops {
-            return false;
+        self.statistic == other.statistic
+            && self.ops == other.ops
+            && self.deltas.len() == other.deltas.len()
+            && self.tensors.len() == other.tensors.len()
+            && self.perms.len() == other.perms.len()
+            && self.placeholders.len() == other.placeholders.len()
+            && self.key() == other.key()
+    }
+
+    pub fn is_normal_order(&self) -> bool {
+        is_normal_order(self)
+    }
+
+    /// The operators sorted into normal order with the permutation sign and
+    /// no contraction terms: quasi-creators left of quasi-annihilators, on
+    /// the vacuum of each operator, creators first within either group and
+    /// then by index name. Two equal fermionic operators give a zero
+    /// coefficient. Placeholders stay where they are, the operators between
+    /// two of them are sorted on their own.
+    pub fn normal_order_permuted(&self) -> Expr {
+        let key = |o: &Op| {
+            (
+                is_quasi_annihilator(o),
+                o.action == Action::Annihilate,
+                o.index.name(),
+                o.space().id(),
+            )
+        };
+        let mut e = self.clone();
+        let mut odd = false;
+        let mut repeated = false;
+        let mut start = 0;
+        let cuts = self.placeholders.iter().map(|p| p.position);
+        for end in cuts.chain([self.ops.len()]) {
+            // Insertion sort, every swap of neighbours flips the sign
+            let ops = &mut e.ops[start..end];
+            for k in 1..ops.len() {
+                let mut j = k;
+                while j > 0 && key(&ops[j - 1]) > key(&ops[j]) {
+                    ops.swap(j - 1, j);
+                    odd = !odd;
+                    j -= 1;
+                }
+            }
+            repeated |= ops.windows(2).any(|w| w[0] == w[1]);
+            start = end;
         }
-        if self.deltas.len() != other.deltas.len() {
-            return false;
+        if self.is_fermi() {
+            if repeated {
+                e.coeff = 0.0;
+            } else if odd {
+                e.coeff = -e.coeff;
+            }
         }
+        e
+    }
 
-        let mut d1: Vec<_> = self.deltas.iter().map(|d| d.canonical()).collect();
-        let mut d2: Vec<_> = other.deltas.iter().map(|d| d.canonical()).collect();
-        d1.sort();
-        d2.sort();
+    /// Product of two terms, fails on mismatched statistics or when the
+    /// left side mixes vacua.
+    /// A dummy index of either side whose name shows up on the other side
+    /// is renamed to a fresh one first, free indices are kept as they are.
+    pub fn try_mul(mut self, mut rhs: Expr) -> Result<Expr, Error> {
+        if self.statistic != rhs.statistic {
+            return Err(Error::MismatchedStatistics {
+                left: self.statistic,
+                right: rhs.statistic,
+            });
+        }
+        self.separate_dummies(&mut rhs)?;
+        self.coeff *= rhs.coeff;
+        let shift = self.ops.len();
+        self.placeholders
+            .extend(rhs.placeholders.into_iter().map(|p| Placeholder {
+                position: p.position + shift,
+                ..p
+            }));
+        self.ops.extend(rhs.ops);
+        self.deltas.extend(rhs.deltas);
+        self.tensors.extend(rhs.tensors);
+        self.perms.extend(rhs.perms);
+        Ok(self)
+    }
 
-        d1 == d2
+    /// The term with placeholder `k` replaced by `rhs`, dummies separated
+    /// as in `try_mul`. Statistics only have to agree when both sides
+    /// carry operators.
+    pub(crate) fn splice(&self, k: usize, mut rhs: Expr) -> Result<Expr, Error> {
+        let mut term = self.clone();
+        let at = term.placeholders.remove(k).position;
+        if term.ops.is_empty() {
+            term.statistic = rhs.statistic;
+        } else if !rhs.ops.is_empty() && term.statistic != rhs.statistic {
+            return Err(Error::MismatchedStatistics {
+                left: term.statistic,
+                right: rhs.statistic,
+            });
+        }
+        term.separate_dummies(&mut rhs)?;
+        let n = rhs.ops.len();
+        for p in &mut term.placeholders[k..] {
+            p.position += n;
+        }
+        let inserted = rhs.placeholders.into_iter().map(|p| Placeholder {
+            position: p.position + at,
+            ..p
+        });
+        term.placeholders.splice(k..k, inserted);
+        term.ops.splice(at..at, rhs.ops);
+        term.coeff *= rhs.coeff;
+        term.deltas.extend(rhs.deltas);
+        term.tensors.extend(rhs.tensors);
+        term.perms.extend(rhs.perms);
+        Ok(term)
     }
 
-    pub fn is_normal_order(&self) -> bool {
-        is_normal_order(self)
+    /// Rename the dummies of either side whose name shows up on the other,
+    /// fails when the left side mixes vacua
+    fn separate_dummies(&mut self, rhs: &mut Expr) -> Result<(), Error> {
+        let mut factory = IndexFactory::for_expr(self, self.vacuum()?);
+        factory.avoid(rhs);
+        let names = |e: &Expr| e.indices().map(|i| i.name()).collect::<Vec<_>>();
+        let lhs_names = names(self);
+        for d in rhs.dummy_indices() {
+            if lhs_names.contains(&d.name()) {
+                rhs.rename(d, factory.fresh_like(d));
+            }
+        }
+        let rhs_names = names(rhs);
+        for d in self.dummy_indices() {
+            if rhs_names.contains(&d.name()) {
+                self.rename(d, factory.fresh_like(d));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -131,15 +478,37 @@ impl Mul<Op> for Expr {
     }
 }
 
+/// 1 double * Tensor
+impl Mul<Tensor> for f64 {
+    type Output = Expr;
+    fn mul(self, tensor: Tensor) -> Self::Output {
+        Expr::new().set_coeff(self).with_tensor(tensor)
+    }
+}
+
+/// 2 Tensor * Op = Expr
+impl Mul<Op> for Tensor {
+    type Output = Expr;
+    fn mul(self, rhs: Op) -> Self::Output {
+        Expr::new().with_tensor(self).with_op(rhs)
+    }
+}
+
+// 3. Expr * Tensor -> Expr
+impl Mul<Tensor> for Expr {
+    type Output = Self;
+    fn mul(mut self, rhs: Tensor) -> Self {
+        self.append_tensor(rhs);
+        self
+    }
+}
+
 // 4. Expr * Expr -> Expr
+/// Panics on mismatched statistics, `try_mul` reports them instead
 impl Mul<Expr> for Expr {
     type Output = Self;
-    fn mul(mut self, rhs: Expr) -> Self {
-        assert_eq!(self.statistic, rhs.statistic);
-        self.coeff *= rhs.coeff;
-        self.ops.extend(rhs.ops);
-        self.deltas.extend(rhs.deltas);
-        self
+    fn mul(self, rhs: Expr) -> Self {
+        self.try_mul(rhs).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
@@ -161,6 +530,11 @@ impl Expr {
             s.push_str(&d.to_latex());
         }
 
+        // Tensor part
+        for t in &self.tensors {
+            s.push_str(&t.to_latex());
+        }
+
         // Action part
         let (creates, annihilates): (Vec<_>, Vec<_>) =
             self.ops.iter().partition(|op| op.action == Action::Create);
@@ -186,39 +560,19 @@ impl Expr {
     }
 
     pub fn to_latex(&self) -> String {
-        let mut s = String::new();
-
-        if self.coeff == 1.0 {
-            if self.deltas.is_empty() && self.ops.is_empty() {
-                s.push_str("1");
-            }
-        } else if self.coeff == -1.0 {
-            s.push_str("-");
-        } else {
-            s.push_str(&format!("{}", self.coeff));
-        }
-
-        for d in &self.deltas {
-            s.push_str(&d.to_latex());
-        }
-
-        for op in &self.ops {
-            s.push_str(&op.to_latex(self.statistic));
-        }
-
-        s
+        self.format(Format::Latex)
     }
 }
 
 /// is normal order
 pub fn is_normal_order(expr: &Expr) -> bool {
-    expr.ops.windows(2).all(|w| !can_contract(&w[0], &w[1]))
+    expr.ops.windows(2).all(|w| !is_out_of_order(&w[0], &w[1]))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::attr::Vacuum;
+    use crate::attr::{Space, Vacuum};
     use crate::index::Index;
     use crate::op::{fannx, fcrex};
 
@@ -261,4 +615,87 @@ mod tests {
         let expr2: Expr = ap2 * cp1;
         assert!(!is_normal_order(&expr2));
     }
+
+    #[test]
+    fn test_try_mul() {
+        let p = Index::new("p").build().unwrap();
+        let fermi = 1.0 * fcrex(p);
+        let bose = (1.0 * fannx(p)).set_statistic(Statistics::BoseEinstein);
+        assert_eq!(
+            fermi.clone().try_mul(bose).unwrap_err(),
+            Error::MismatchedStatistics {
+                left: Statistics::FermiDirac,
+                right: Statistics::BoseEinstein
+            }
+        );
+        assert_eq!(fermi.clone().try_mul(fermi).unwrap().ops.len(), 2);
+    }
+
+    #[test]
+    fn test_mul_renames_dummies() {
+        let t2 = crate::parser::parse_term("t[a,b,i,j] a+[a] a+[b] a[j] a[i]").unwrap();
+        let tt = t2.clone() * t2.clone();
+        assert_eq!(
+            tt.to_text(),
+            "t[a,b,i,j] t[c,d,k,l] a+[a] a+[b] a[j] a[i] a+[c] a+[d] a[l] a[k]"
+        );
+        // Free indices are shared on purpose, the dummy i of the left moves
+        let r = crate::parser::parse_term("f[i,j] a+[i] a[a]").unwrap();
+        let rhs = crate::parser::parse_term("x[i,b]").unwrap();
+        assert_eq!((r * rhs).to_text(), "f[k,j] x[i,b] a+[k] a[a]");
+
+        let mut f = IndexFactory::for_expr(&t2, Vacuum::Fermi);
+        assert_eq!(f.fresh(Space::Occupied).name(), "k");
+        assert_eq!(f.fresh(Space::Occupied).name(), "l");
+        assert_eq!(f.fresh(Space::Occupied).name(), "i1");
+        assert_eq!(f.fresh(Space::Virtual).vacuum(), Vacuum::Fermi);
+    }
+
+    #[test]
+    fn test_relabel() {
+        let e = crate::parser::parse_term("f[i,j] t[j,a] delta[i,k] a+[a] a[j]").unwrap();
+        let idx = |n: &str| e.indices().find(|i| i.name() == n).unwrap();
+        let swap = HashMap::from([(idx("i"), idx("j")), (idx("j"), idx("i"))]);
+        assert_eq!(
+            e.relabel(&swap).unwrap().to_text(),
+            "delta[j,k] f[j,i] t[i,a] a+[a] a[i]"
+        );
+        let general = HashMap::from([(idx("a"), idx("a").with_space(Space::General))]);
+        assert!(e.relabel(&general).is_err());
+        // i -> j would merge the dummies i and j, x is not in the term
+        let merge = HashMap::from([(idx("i"), idx("j"))]);
+        assert!(matches!(
+            e.relabel(&merge),
+            Err(Error::InvalidRelabeling { .. })
+        ));
+        let x = idx("i").renamed("x");
+        let missing = HashMap::from([(x, idx("i"))]);
+        assert!(matches!(
+            e.relabel(&missing),
+            Err(Error::InvalidRelabeling { index, .. }) if index == x
+        ));
+
+        // delta[i,k] becomes delta[i,i] = 1, occupied j and virtual a never coincide
+        let k = idx("k");
+        let e = e.substitute_index(k, idx("i")).unwrap().unwrap();
+        assert_eq!(e.to_text(), "f[i,j] t[j,a] a+[a] a[j]");
+        assert!(e.substitute_index(idx("j"), idx("a")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_normal_order_permuted() {
+        let term = |s: &str| crate::parser::parse_term(s).unwrap();
+        // Three transpositions, no contraction terms
+        let e = term("2 * g[p,q,r,s] a[q] a+[p] a[r] a+[s]").normal_order_permuted();
+        assert_eq!(e.to_text(), "-2 * g[p,q,r,s] a+[p] a+[s] a[q] a[r]");
+        assert!(e.is_normal_order());
+
+        // On the Fermi vacuum a[i] is a quasi-creator
+        let e = term("t[a,i] a[a] a+[b] a[i]").normal_order_permuted();
+        assert_eq!(e.to_text(), "t[a,i] a+[b] a[i] a[a]");
+
+        assert_eq!(term("a+[p] a[q] a+[p]").normal_order_permuted().coeff, 0.0);
+        let e = term("b[p] b+[q] b[r]").normal_order_permuted();
+        assert_eq!(e.to_text(), "b+[q] b[p] b[r]");
+    }
 }
@@ -0,0 +1,370 @@
+/// src/factorize.rs
+use crate::attr::{Occupancy, Space};
+use crate::expr::Expr;
+use crate::index::Index;
+use crate::result_expr::ResultExpr;
+use crate::tensor::Tensor;
+use std::collections::HashMap;
+
+/// CostModel gives the size of each orbital space.
+/// The cost of a pairwise contraction is the product of the dimensions
+/// of every index involved in it (one multiply-add per loop iteration).
+#[derive(Debug, Clone, Copy)]
+pub struct CostModel {
+    pub nocc: usize,
+    pub nvir: usize,
+}
+
+impl CostModel {
+    pub fn new(nocc: usize, nvir: usize) -> Self {
+        Self { nocc, nvir }
+    }
+
+    pub fn dim(&self, space: Space) -> f64 {
+        match space.occupancy() {
+            Some(Occupancy::Hole) => self.nocc as f64,
+            Some(Occupancy::Particle) => self.nvir as f64,
+            _ => (self.nocc + self.nvir) as f64,
+        }
+    }
+
+    pub fn flops(&self, indices: &[Index]) -> f64 {
+        indices.iter().map(|i| self.dim(i.space())).product()
+    }
+}
+
+/// Intermediate = left * right, summed over the indices not kept in `tensor`
+#[derive(Debug, Clone)]
+pub struct Intermediate {
+    pub tensor: Tensor,
+    pub left: Tensor,
+    pub right: Tensor,
+    pub flops: f64,
+}
+
+/// Factorization of a ResultExpr into a list of pairwise contractions.
+/// Every term keeps its coefficient, deltas and ops, but its tensors are
+/// replaced by the final intermediate (terms with one tensor are unchanged).
+/// Intermediates are listed in evaluation order and shared between terms.
+#[derive(Debug, Clone)]
+pub struct Factorization {
+    pub intermediates: Vec<Intermediate>,
+    pub terms: Vec<Expr>,
+}
+
+impl Factorization {
+    /// Total cost of all intermediates
+    pub fn flops(&self) -> f64 {
+        self.intermediates.iter().map(|x| x.flops).sum()
+    }
+
+    pub fn intermediates(&self) -> &[Intermediate] {
+        &self.intermediates
+    }
+    pub fn terms(&self) -> &[Expr] {
+        &self.terms
+    }
+}
+
+/// Factorize every term of `expr` into the pairwise contraction order with
+/// the lowest cost. `output` are the free indices of the result, terms
+/// with permutation operators are expanded first.
+pub fn factorize(expr: &ResultExpr, output: &[Index], cost: &CostModel) -> Factorization {
+    let mut f = Factorizer {
+        cost,
+        intermediates: Vec::new(),
+        known: HashMap::new(),
+    };
+    let terms = expr
+        .expand_permutations()
+        .terms()
+        .iter()
+        .map(|t| f.factorize_term(t, output))
+        .collect();
+    Factorization {
+        intermediates: f.intermediates,
+        terms,
+    }
+}
+
+struct Factorizer<'a> {
+    cost: &'a CostModel,
+    intermediates: Vec<Intermediate>,
+    /// Canonical key -> position in `intermediates`
+    known: HashMap<String, usize>,
+}
+
+impl Factorizer<'_> {
+    fn factorize_term(&mut self, term: &Expr, output: &[Index]) -> Expr {
+        let tensors = term.tensors();
+        let n = tensors.len();
+        if n <= 1 {
+            return term.clone();
+        }
+
+        // Indices which must survive every contraction
+        let mut external: Vec<&Index> = output.iter().collect();
+        for d in term.deltas() {
+            external.push(&d.a);
+            external.push(&d.b);
+        }
+        external.extend(term.ops().iter().map(|o| &o.index));
+
+        // Indices kept by the intermediate of every subset of tensors
+        let full = (1usize << n) - 1;
+        let kept: Vec<Vec<Index>> = (0..=full)
+            .map(|mask| kept_indices(tensors, mask, &external))
+            .collect();
+
+        // Best contraction cost and split of every subset
+        let mut best: Vec<(f64, usize)> = vec![(0.0, 0); full + 1];
+        for mask in 1..=full {
+            if mask.count_ones() == 1 {
+                continue;
+            }
+            best[mask] = (f64::INFINITY, 0);
+            // Enumerate proper subsets containing the lowest tensor only once
+            let low = mask & mask.wrapping_neg();
+            let mut sub = (mask - 1) & mask;
+            while sub > 0 {
+                if sub & low != 0 {
+                    let rest = mask ^ sub;
+                    let flops = self.cost.flops(&union(&kept[sub], &kept[rest]));
+                    let total = best[sub].0 + best[rest].0 + flops;
+                    if total < best[mask].0 {
+                        best[mask] = (total, sub);
+                    }
+                }
+                sub = (sub - 1) & mask;
+            }
+        }
+
+        let mut result = term.clone();
+        result.tensors = vec![self.build(tensors, &kept, &best, full)];
+        result
+    }
+
+    /// Emit the intermediates of a subset and return the tensor standing for it
+    fn build(
+        &mut self,
+        tensors: &[Tensor],
+        kept: &[Vec<Index>],
+        best: &[(f64, usize)],
+        mask: usize,
+    ) -> Tensor {
+        if mask.count_ones() == 1 {
+            return tensors[mask.trailing_zeros() as usize].clone();
+        }
+        let sub = best[mask].1;
+        let left = self.build(tensors, kept, best, sub);
+        let right = self.build(tensors, kept, best, mask ^ sub);
+        self.intern(left, right, &kept[mask])
+    }
+
+    /// Reuse an equivalent intermediate or register a new one
+    fn intern(&mut self, left: Tensor, right: Tensor, kept: &[Index]) -> Tensor {
+        let (left, right) = if operand_shape(&left, kept) <= operand_shape(&right, kept) {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        // Result indices follow their first appearance in the operands
+        let indices: Vec<Index> = left
+            .indices
+            .iter()
+            .chain(&right.indices)
+            .filter(|i| kept.iter().any(|k| k.name() == i.name()))
+            .fold(Vec::new(), |mut acc, i| {
+                if !acc.iter().any(|a: &Index| a.name() == i.name()) {
+                    acc.push(*i);
+                }
+                acc
+            });
+
+        let key = intermediate_key(&left, &right, &indices);
+        if let Some(&k) = self.known.get(&key) {
+            return self.intermediates[k].tensor.clone();
+        }
+
+        let all = union(&left.indices, &right.indices);
+        let tensor = Tensor::new(format!("X{}", self.intermediates.len()), indices);
+        self.known.insert(key, self.intermediates.len());
+        self.intermediates.push(Intermediate {
+            tensor: tensor.clone(),
+            left,
+            right,
+            flops: self.cost.flops(&all),
+        });
+        tensor
+    }
+}
+
+/// Indices of a subset of tensors which also appear outside of it
+fn kept_indices(tensors: &[Tensor], mask: usize, external: &[&Index]) -> Vec<Index> {
+    let mut kept: Vec<Index> = Vec::new();
+    for (k, t) in tensors.iter().enumerate() {
+        if mask & (1 << k) == 0 {
+            continue;
+        }
+        for idx in t.indices() {
+            if kept.iter().any(|i| i.name() == idx.name()) {
+                continue;
+            }
+            let outside = external.iter().any(|e| e.name() == idx.name())
+                || tensors.iter().enumerate().any(|(l, o)| {
+                    mask & (1 << l) == 0 && o.indices().iter().any(|i| i.name() == idx.name())
+                });
+            if outside {
+                kept.push(*idx);
+            }
+        }
+    }
+    kept
+}
+
+/// Union of two index lists, keeping the order of first appearance
+fn union(a: &[Index], b: &[Index]) -> Vec<Index> {
+    let mut all = a.to_vec();
+    for idx in b {
+        if !all.iter().any(|i| i.name() == idx.name()) {
+            all.push(*idx);
+        }
+    }
+    all
+}
+
+/// Operand written with its summed indices blanked, used to order operands
+fn operand_shape(t: &Tensor, kept: &[Index]) -> String {
+    let names: Vec<&str> = t
+        .indices
+        .iter()
+        .map(|i| {
+            if kept.iter().any(|k| k.name() == i.name()) {
+                i.name()
+            } else {
+                "*"
+            }
+        })
+        .collect();
+    format!("{}[{}]", t.label, names.join(","))
+}
+
+/// Key of an intermediate, invariant under renaming of its summed indices
+fn intermediate_key(left: &Tensor, right: &Tensor, kept: &[Index]) -> String {
+    let mut dummies: Vec<&str> = Vec::new();
+    let mut operands = Vec::with_capacity(2);
+    for t in [left, right] {
+        let mut names = Vec::with_capacity(t.rank());
+        for i in t.indices() {
+            if kept.iter().any(|k| k.name() == i.name()) {
+                names.push(i.name().to_string());
+                continue;
+            }
+            let pos = match dummies.iter().position(|d| *d == i.name()) {
+                Some(pos) => pos,
+                None => {
+                    dummies.push(i.name());
+                    dummies.len() - 1
+                }
+            };
+            names.push(format!("#{}:{}", pos, i.space()));
+        }
+        operands.push(format!("{}[{}]", t.label, names.join(",")));
+    }
+    let out: Vec<&str> = kept.iter().map(|i| i.name()).collect();
+    format!("{}->[{}]", operands.join("*"), out.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attr::Vacuum;
+    use crate::eval::{DenseTensor, Evaluator};
+
+    fn idx(name: &str, space: Space) -> Index {
+        Index::new(name)
+            .with_space(space)
+            .with_vacuum(Vacuum::Fermi)
+            .build()
+            .unwrap()
+    }
+
+    fn filled(shape: Vec<usize>, seed: f64) -> DenseTensor {
+        let len: usize = shape.iter().product();
+        let data = (0..len).map(|k| ((k as f64 + seed) * 0.37).sin()).collect();
+        DenseTensor::from_vec(shape, data).unwrap()
+    }
+
+    #[test]
+    fn test_cheapest_order() {
+        // r_{ia} = A_{ab} B_{bc} t_{ic}: contracting B with t first avoids v^3
+        let (i, a, b, c) = (
+            idx("i", Space::Occupied),
+            idx("a", Space::Virtual),
+            idx("b", Space::Virtual),
+            idx("c", Space::Virtual),
+        );
+        let term = 1.0
+            * Tensor::new("A", vec![a, b])
+            * Tensor::new("B", vec![b, c])
+            * Tensor::new("t", vec![i, c]);
+        let f = factorize(
+            &ResultExpr::from_expr(term),
+            &[i, a],
+            &CostModel::new(2, 10),
+        );
+        assert_eq!(f.intermediates.len(), 2);
+        assert_eq!(f.intermediates[0].left.label, "B");
+        assert_eq!(f.intermediates[0].right.label, "t");
+        assert_eq!(f.flops(), 400.0);
+        assert_eq!(f.terms[0].tensors, vec![f.intermediates[1].tensor.clone()]);
+    }
+
+    #[test]
+    fn test_open_operators_kept() {
+        // A_{ab} t_{ib} a^+_a a_i: a and i belong to the operator string
+        let (i, a, b) = (
+            idx("i", Space::Occupied),
+            idx("a", Space::Virtual),
+            idx("b", Space::Virtual),
+        );
+        let term = 1.0
+            * Tensor::new("A", vec![a, b])
+            * Tensor::new("t", vec![i, b])
+            * crate::op::fcrex(a)
+            * crate::op::fannx(i);
+        let f = factorize(&ResultExpr::from_expr(term), &[], &CostModel::new(2, 3));
+        assert_eq!(f.intermediates.len(), 1);
+        assert_eq!(f.intermediates[0].tensor.indices, vec![a, i]);
+        assert_eq!(f.terms[0].ops().len(), 2);
+        assert_eq!(f.terms[0].tensors, vec![f.intermediates[0].tensor.clone()]);
+    }
+
+    #[test]
+    fn test_shared_intermediate() {
+        // Both terms contain g_{ijab} t_{jb} with different dummy names
+        let (i, j, k, a, b, c) = (
+            idx("i", Space::Occupied),
+            idx("j", Space::Occupied),
+            idx("k", Space::Occupied),
+            idx("a", Space::Virtual),
+            idx("b", Space::Virtual),
+            idx("c", Space::Virtual),
+        );
+        let t1 = 1.0 * Tensor::new("g", vec![i, j, a, b]) * Tensor::new("t", vec![j, b]);
+        let t2 = 0.5 * Tensor::new("g", vec![i, k, a, c]) * Tensor::new("t", vec![k, c]);
+        let expr: ResultExpr = [t1, t2].into_iter().collect();
+        let output = [i, a];
+        let f = factorize(&expr, &output, &CostModel::new(2, 3));
+        assert_eq!(f.intermediates.len(), 1);
+
+        let ev = Evaluator::new(2, 3)
+            .bind("g", filled(vec![2, 2, 3, 3], 1.0))
+            .bind("t", filled(vec![2, 3], 2.0));
+        let naive = ev.evaluate(&expr, &output).unwrap();
+        let fast = ev.evaluate_factorized(&f, &output).unwrap();
+        for (x, y) in naive.data().iter().zip(fast.data()) {
+            assert!((x - y).abs() < 1e-12);
+        }
+    }
+}
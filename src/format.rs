@@ -0,0 +1,425 @@
+/// src/format.rs
+use crate::attr::{Action, Space};
+use crate::expr::{Expr, Summation};
+use crate::index::Index;
+use crate::op::Op;
+use crate::parser::{infer_space, infer_vacuum, vacuum_tag};
+use crate::placeholder::Placeholder;
+use crate::result_expr::ResultExpr;
+use std::fmt;
+
+/// Output formats of Expr and ResultExpr
+/// Latex: publication LaTeX, \frac{1}{4} g_{pqrs} \hat a^\dagger_{p} ...
+/// Text: canonical plain text, read back by `parser::parse`
+/// Unicode: compact form for terminal output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Latex,
+    Text,
+    Unicode,
+}
+
+impl Expr {
+    pub fn format(&self, format: Format) -> String {
+        let body = term_body(self, format);
+        let body = if self.coeff < 0.0 {
+            format!("-{}", body)
+        } else {
+            body
+        };
+        match format {
+            Format::Text => text_vacuum(self.indices()) + &body,
+            _ => body,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        self.format(Format::Text)
+    }
+
+    pub fn to_unicode(&self) -> String {
+        self.format(Format::Unicode)
+    }
+
+    /// LaTeX with the summed indices written out, \frac{1}{2} \sum_{ia} f_{ia} t_{ia}
+    pub fn to_latex_summed(&self, summation: &Summation) -> String {
+        let body = summed_body(self, summation);
+        if self.coeff < 0.0 {
+            format!("-{}", body)
+        } else {
+            body
+        }
+    }
+}
+
+impl ResultExpr {
+    pub fn format(&self, format: Format) -> String {
+        let body = self.join(|t| term_body(t, format));
+        match format {
+            Format::Text => text_vacuum(self.terms().iter().flat_map(|t| t.indices())) + &body,
+            _ => body,
+        }
+    }
+
+    /// LaTeX with the summed indices of every term written out
+    pub fn to_latex_summed(&self, summation: &Summation) -> String {
+        self.join(|t| summed_body(t, summation))
+    }
+
+    /// Signed sum of the term bodies
+    fn join(&self, body: impl Fn(&Expr) -> String) -> String {
+        let mut s = String::new();
+        for term in self.terms() {
+            let body = body(term);
+            if body.is_empty() {
+                continue;
+            }
+            let minus = term.coeff < 0.0;
+            match (s.is_empty(), minus) {
+                (true, false) => {}
+                (true, true) => s.push('-'),
+                (false, false) => s.push_str(" + "),
+                (false, true) => s.push_str(" - "),
+            }
+            s.push_str(&body);
+        }
+        if s.is_empty() { "0".to_string() } else { s }
+    }
+
+    pub fn to_text(&self) -> String {
+        self.format(Format::Text)
+    }
+
+    pub fn to_unicode(&self) -> String {
+        self.format(Format::Unicode)
+    }
+
+    /// LaTeX aligned environment with `per_line` terms on every line
+    pub fn to_latex_aligned(&self, per_line: usize) -> String {
+        let per_line = per_line.max(1);
+        let mut lines: Vec<String> = Vec::new();
+        for (k, term) in self.terms().iter().enumerate() {
+            let start = k % per_line == 0;
+            if start {
+                lines.push(String::from("& "));
+            }
+            let sign = match (k == 0, start, term.coeff < 0.0) {
+                (true, _, false) => "",
+                (true, _, true) => "-",
+                (false, true, false) => "+ ",
+                (false, true, true) => "- ",
+                (false, false, false) => " + ",
+                (false, false, true) => " - ",
+            };
+            let line = lines.last_mut().unwrap();
+            line.push_str(sign);
+            line.push_str(&term_body(term, Format::Latex));
+        }
+        if lines.is_empty() {
+            lines.push("& 0".to_string());
+        }
+        format!(
+            "\\begin{{aligned}}\n{}\n\\end{{aligned}}",
+            lines.join(" \\\\\n")
+        )
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_unicode())
+    }
+}
+
+impl fmt::Display for ResultExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_unicode())
+    }
+}
+
+/// Term without its sign: |coeff| followed by deltas, tensors and ops
+fn term_body(e: &Expr, format: Format) -> String {
+    body_with(e, format, Vec::new())
+}
+
+/// LaTeX term body with a \sum over its summed indices in front of the factors
+fn summed_body(e: &Expr, summation: &Summation) -> String {
+    let summed = e.summed_indices(summation);
+    if summed.is_empty() {
+        return term_body(e, Format::Latex);
+    }
+    let idx: String = summed.iter().map(|i| latex_index(i.name())).collect();
+    body_with(e, Format::Latex, vec![format!("\\sum_{{{}}}", idx)])
+}
+
+/// Operators with the placeholders at their positions
+fn operator_string(
+    e: &Expr,
+    op: impl Fn(&Op) -> String,
+    placeholder: impl Fn(&Placeholder) -> String,
+) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut pending = e.placeholders().iter().peekable();
+    for (k, o) in e.ops().iter().enumerate() {
+        while let Some(p) = pending.next_if(|p| p.position <= k) {
+            out.push(placeholder(p));
+        }
+        out.push(op(o));
+    }
+    out.extend(pending.map(placeholder));
+    out
+}
+
+fn body_with(e: &Expr, format: Format, mut factors: Vec<String>) -> String {
+    match format {
+        Format::Latex => {
+            factors.extend(e.perms.iter().map(|p| p.to_latex()));
+            factors.extend(e.deltas.iter().map(|d| d.to_latex()));
+            factors.extend(e.tensors.iter().map(|t| t.to_latex()));
+            factors.extend(operator_string(
+                e,
+                |o| o.to_latex(e.statistic),
+                |p| p.to_latex(),
+            ));
+        }
+        Format::Text => {
+            for p in &e.perms {
+                let pairs: Vec<String> = p
+                    .pairs
+                    .iter()
+                    .map(|(a, b)| format!("{},{}", text_index(a), text_index(b)))
+                    .collect();
+                factors.push(format!("P({})", pairs.join("|")));
+            }
+            for d in &e.deltas {
+                if d.a != d.b {
+                    factors.push(format!("delta[{},{}]", text_index(&d.a), text_index(&d.b)));
+                }
+            }
+            for t in &e.tensors {
+                let idx: Vec<String> = t.indices.iter().map(text_index).collect();
+                factors.push(format!("{}[{}]", t.label, idx.join(",")));
+            }
+            let symbol = e.statistic.symbol();
+            factors.extend(operator_string(
+                e,
+                |o| {
+                    let dagger = if o.action == Action::Create { "+" } else { "" };
+                    format!("{}{}[{}]", symbol, dagger, text_index(&o.index))
+                },
+                |p| p.name.clone(),
+            ));
+        }
+        Format::Unicode => {
+            for p in &e.perms {
+                let pairs: Vec<String> = p
+                    .pairs
+                    .iter()
+                    .map(|(a, b)| unicode_index(a.name()) + &unicode_index(b.name()))
+                    .collect();
+                factors.push(format!("P({})", pairs.join("|")));
+            }
+            for d in &e.deltas {
+                if d.a != d.b {
+                    factors.push(format!(
+                        "δ({},{})",
+                        unicode_index(d.a.name()),
+                        unicode_index(d.b.name())
+                    ));
+                }
+            }
+            for t in &e.tensors {
+                let idx: Vec<String> = t.indices.iter().map(|i| unicode_index(i.name())).collect();
+                factors.push(format!("{}({})", t.label, idx.join(",")));
+            }
+            factors.extend(operator_string(
+                e,
+                |o| {
+                    let dagger = if o.action == Action::Create {
+                        "†"
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "{}{}({})",
+                        e.statistic.symbol(),
+                        dagger,
+                        unicode_index(o.name())
+                    )
+                },
+                |p| p.name.clone(),
+            ));
+        }
+    }
+    factors.retain(|f| !f.is_empty());
+
+    let c = e.coeff.abs();
+    let coeff = if c == 1.0 && !factors.is_empty() {
+        None
+    } else {
+        Some(match (format, fraction(c)) {
+            (Format::Text, _) => format!("{}", c),
+            (Format::Latex, Some((n, d))) if d > 1 => format!("\\frac{{{}}}{{{}}}", n, d),
+            (Format::Unicode, Some((n, d))) if d > 1 => format!("{}/{}", n, d),
+            _ => format!("{}", c),
+        })
+    };
+
+    match (coeff, format) {
+        (None, _) => factors.join(" "),
+        (Some(c), _) if factors.is_empty() => c,
+        (Some(c), Format::Text) => format!("{} * {}", c, factors.join(" ")),
+        (Some(c), _) => format!("{} {}", c, factors.join(" ")),
+    }
+}
+
+/// x = n / d with a small denominator, None otherwise
+pub fn fraction(x: f64) -> Option<(i64, i64)> {
+    (1..=64i64).find_map(|d| {
+        let n = (x * d as f64).round();
+        if (x * d as f64 - n).abs() < 1e-10 && n.abs() < 1e12 {
+            Some((n as i64, d))
+        } else {
+            None
+        }
+    })
+}
+
+/// p_1, p1 -> p_1; p_12 -> p_{12}; p -> p
+pub fn latex_index(name: &str) -> String {
+    let stem: String = name.chars().take_while(|c| c.is_alphabetic()).collect();
+    let sub: String = name
+        .chars()
+        .skip(stem.chars().count())
+        .filter(|c| c.is_alphanumeric())
+        .collect();
+    match sub.chars().count() {
+        0 => stem,
+        1 => format!("{}_{}", stem, sub),
+        _ => format!("{}_{{{}}}", stem, sub),
+    }
+}
+
+/// p_1 -> p₁
+pub fn unicode_index(name: &str) -> String {
+    const SUBSCRIPTS: [char; 10] = ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'];
+    name.chars()
+        .filter(|c| *c != '_')
+        .map(|c| match c.to_digit(10) {
+            Some(d) => SUBSCRIPTS[d as usize],
+            None => c,
+        })
+        .collect()
+}
+
+/// `@mr ` when the parser would put the indices on another vacuum
+fn text_vacuum(indices: impl Iterator<Item = Index>) -> String {
+    let indices: Vec<Index> = indices.collect();
+    match indices.first() {
+        Some(i) if infer_vacuum(indices.iter().map(|i| i.space())) != i.vacuum() => {
+            format!("@{} ", vacuum_tag(i.vacuum()))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Index name, with its space spelled out when the parser would infer another one
+fn text_index(i: &Index) -> String {
+    if infer_space(i.name()) == i.space() {
+        return i.name().to_string();
+    }
+    let tag = match i.space() {
+        Space::General => "g",
+        Space::Occupied => "o",
+        Space::Virtual => "v",
+        Space::DoublyOccupied => "c",
+        s => s.name(),
+    };
+    format!("{}:{}", i.name(), tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+
+    #[test]
+    fn test_latex() {
+        let h = parse("0.5 * f[p_1,q] a+[p_1] a[q] - 1/4 g[pq,rs] a+[p] a+[q] a[s] a[r]").unwrap();
+        assert_eq!(
+            h.to_latex(),
+            "\\frac{1}{2} f_{p_1q} \\hat a^\\dagger_{p_1} \\hat a_{q} - \\frac{1}{4} g_{pqrs} \\hat a^\\dagger_{p} \\hat a^\\dagger_{q} \\hat a_{s} \\hat a_{r}"
+        );
+        assert_eq!(
+            parse("f[p,q] - delta[p,q]").unwrap().to_latex_aligned(1),
+            "\\begin{aligned}\n& f_{pq} \\\\\n& - \\delta_{pq}\n\\end{aligned}"
+        );
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let input =
+            "-0.1 * delta[p,q_2] f[p_1,q] a+[p_1] a[q] + 1/3 g[p,q,r,s] a+[p] a+[q] a[s] a[r] + 2";
+        let e = parse(input).unwrap();
+        let text = e.to_text();
+        assert_eq!(
+            text,
+            "-0.1 * delta[p,q_2] f[p_1,q] a+[p_1] a[q] + 0.3333333333333333 * g[p,q,r,s] a+[p] a+[q] a[s] a[r] + 2"
+        );
+        let back = parse(&text).unwrap();
+        assert_eq!(back.terms().len(), e.terms().len());
+        for (a, b) in e.terms().iter().zip(back.terms()) {
+            assert!(a.is_similar(b));
+            assert_eq!(a.coeff, b.coeff);
+        }
+    }
+
+    #[test]
+    fn test_text_intersected_space() {
+        use crate::attr::{Occupancy, Space};
+        use crate::parser::parse_with_spaces;
+        use std::collections::HashMap;
+        let parts: Vec<Space> = ["fmt_a", "fmt_b", "fmt_c"]
+            .iter()
+            .map(|name| Space::define(name, Space::Occupied, Occupancy::Hole).unwrap())
+            .collect();
+        let left = Space::union("fmt_abc", &parts).unwrap();
+        let right = Space::union("fmt_bcv", &[parts[1], parts[2], Space::Virtual]).unwrap();
+        let common = left.intersection(right).unwrap().unwrap();
+        assert_eq!(common.name(), "fmt_abc&fmt_bcv");
+        let spaces = HashMap::from([("x".to_string(), common), ("y".to_string(), common)]);
+        let e = parse_with_spaces("f[x,y] a+[x] a[y]", &spaces).unwrap();
+        let text = e.to_text();
+        assert_eq!(
+            text,
+            "f[x:fmt_abc&fmt_bcv,y:fmt_abc&fmt_bcv] a+[x:fmt_abc&fmt_bcv] a[y:fmt_abc&fmt_bcv]"
+        );
+        let back = parse(&text).unwrap();
+        assert!(back.terms()[0].is_similar(&e.terms()[0]));
+        assert_eq!(back.terms()[0].ops[0].space(), common);
+    }
+
+    #[test]
+    fn test_text_vacuum_and_statistics() {
+        use crate::attr::{Statistics, Vacuum};
+        // Occupied and virtual indices alone would be read on the Fermi vacuum
+        let e = parse("@mr f[i,a] c+[i] c[a]").unwrap();
+        assert_eq!(e.terms()[0].ops[0].vacuum(), Vacuum::MultiReference);
+        assert_eq!(e.terms()[0].statistic, Statistics::Arbitrary);
+        let text = e.to_text();
+        assert_eq!(text, "@mr f[i,a] c+[i] c[a]");
+        let back = parse(&text).unwrap();
+        assert!(back.terms()[0].is_similar(&e.terms()[0]));
+        assert_eq!(back.terms()[0].ops[0].vacuum(), Vacuum::MultiReference);
+        assert_eq!(back.terms()[0].statistic, Statistics::Arbitrary);
+        // The inferred vacuum is not written
+        assert_eq!(parse("@fermi t[i,a]").unwrap().to_text(), "t[i,a]");
+        assert!(parse("@fermi f[p,q]").is_err());
+        assert!(parse("@hf f[i,a]").is_err());
+    }
+
+    #[test]
+    fn test_unicode() {
+        let e = parse("-0.25 * t[i_1,a] b+[a] b[i_1]").unwrap();
+        assert_eq!(e.to_unicode(), "-1/4 t(i₁,a) b†(a) b(i₁)");
+    }
+}
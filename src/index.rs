@@ -1,21 +1,29 @@
 /// src/index.rs
 use crate::attr::{Space, Vacuum};
+use crate::error::Error;
+use crate::expr::Expr;
+use crate::registry;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
 /// Index define the properties of an index in second quantization.
 /// name: The name of the index.
 /// space: The space type of the index (General, Occupied, Virtual).
 /// vacuum: The vacuum type of the index (Physical, Fermi, Bose).
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Only the name is interned, space and vacuum are stored inline, so an
+/// Index is Copy and reading, comparing or hashing it never takes a lock.
+#[derive(Clone, Copy)]
 pub struct Index {
-    pub name: String,
-    pub space: Space,
-    pub vacuum: Vacuum,
+    name: &'static str,
+    space: Space,
+    vacuum: Vacuum,
 }
 
 impl Index {
     pub fn new(name: impl Into<String>) -> Self {
         Self {
-            name: name.into(),
+            name: registry::intern(&name.into()),
             space: Space::General,
             vacuum: Vacuum::Physical,
         }
@@ -31,20 +39,28 @@ impl Index {
         self
     }
 
-    pub fn build(self) -> Result<Self, String> {
+    /// Same space and vacuum under another name
+    pub fn renamed(self, name: &str) -> Self {
+        Self {
+            name: registry::intern(name),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Result<Self, Error> {
         if self.space.is_allowed(self.vacuum) {
             Ok(self)
         } else {
-            Err(format!(
-                "Illegal Space {:?} for Vacuum {:?}",
-                self.space, self.vacuum
-            ))
+            Err(Error::IllegalSpace {
+                space: self.space,
+                vacuum: self.vacuum,
+            })
         }
     }
 
     /// Some interface
-    pub fn name(&self) -> &str {
-        &self.name
+    pub fn name(&self) -> &'static str {
+        self.name
     }
     pub fn space(&self) -> Space {
         self.space
@@ -54,6 +70,153 @@ impl Index {
     }
 }
 
+/// Names are interned, so equal names are the same address
+impl PartialEq for Index {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.name, other.name)
+            && self.space == other.space
+            && self.vacuum == other.vacuum
+    }
+}
+
+impl Eq for Index {}
+
+/// By name, then space and vacuum
+impl Ord for Index {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.name, self.space, self.vacuum).cmp(&(other.name, other.space, other.vacuum))
+    }
+}
+
+impl PartialOrd for Index {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for Index {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.as_ptr().hash(state);
+        self.space.hash(state);
+        self.vacuum.hash(state);
+    }
+}
+
+impl fmt::Debug for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Index")
+            .field("name", &self.name)
+            .field("space", &self.space)
+            .field("vacuum", &self.vacuum)
+            .finish()
+    }
+}
+
+/// Hands out indices whose names are not used yet: i, j, k, l, i1, i2, ..
+/// for occupied, a, b, c, d, a1, .. for virtual and p, q, r, s, p1, .. for
+/// general orbitals, x1, x2, .. for every other space
+#[derive(Debug, Clone)]
+pub struct IndexFactory {
+    vacuum: Vacuum,
+    used: HashSet<&'static str>,
+}
+
+impl IndexFactory {
+    pub fn new(vacuum: Vacuum) -> Self {
+        Self {
+            vacuum,
+            used: HashSet::new(),
+        }
+    }
+
+    /// Factory on `vacuum` avoiding all names of the expression
+    pub fn for_expr(expr: &Expr, vacuum: Vacuum) -> Self {
+        let mut factory = Self::new(vacuum);
+        factory.avoid(expr);
+        factory
+    }
+
+    /// Never hand out the names of `expr`
+    pub fn avoid(&mut self, expr: &Expr) {
+        for i in expr.indices() {
+            self.reserve(i);
+        }
+    }
+
+    pub fn reserve(&mut self, index: Index) {
+        self.used.insert(index.name());
+    }
+
+    /// Fresh index of the space on the vacuum of the factory
+    pub fn fresh(&mut self, space: Space) -> Index {
+        let (letters, stem): (&[&str], &str) = match space {
+            Space::Occupied => (&["i", "j", "k", "l"], "i"),
+            Space::Virtual => (&["a", "b", "c", "d"], "a"),
+            Space::General => (&["p", "q", "r", "s"], "p"),
+            _ => (&[], "x"),
+        };
+        let name = letters
+            .iter()
+            .map(|l| l.to_string())
+            .chain((1..).map(|k| format!("{}{}", stem, k)))
+            .find(|n| !self.used.contains(n.as_str()))
+            .unwrap();
+        let index = Index::new(name).with_space(space).with_vacuum(self.vacuum);
+        self.reserve(index);
+        index
+    }
+
+    /// Fresh index of the same space and vacuum as `index`
+    pub fn fresh_like(&mut self, index: Index) -> Index {
+        let fresh = self.fresh(index.space());
+        fresh.with_vacuum(index.vacuum())
+    }
+}
+
+/// Serialized form of an Index, the interned name owns its string again
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct IndexRepr {
+    name: String,
+    space: Space,
+    vacuum: Vacuum,
+}
+
+#[cfg(feature = "serde")]
+impl From<Index> for IndexRepr {
+    fn from(index: Index) -> Self {
+        Self {
+            name: index.name.to_string(),
+            space: index.space,
+            vacuum: index.vacuum,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<IndexRepr> for Index {
+    fn from(repr: IndexRepr) -> Self {
+        Index::new(repr.name)
+            .with_space(repr.space)
+            .with_vacuum(repr.vacuum)
+    }
+}
+
+/// Written by hand, the derive would tie the 'static name to 'de
+#[cfg(feature = "serde")]
+impl serde::Serialize for Index {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        IndexRepr::from(*self).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Index {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IndexRepr::deserialize(deserializer).map(Index::from)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,8 +230,8 @@ mod tests {
         assert!(res.is_ok());
 
         let idx = res.unwrap();
-        assert_eq!(idx.name, "i");
-        assert_eq!(idx.space, Space::Occupied);
+        assert_eq!(idx.name(), "i");
+        assert_eq!(idx.space(), Space::Occupied);
     }
 
     #[test]
@@ -77,7 +240,13 @@ mod tests {
             .with_space(Space::Occupied)
             .with_vacuum(Vacuum::Physical)
             .build();
-        assert!(res.is_err());
+        assert_eq!(
+            res,
+            Err(Error::IllegalSpace {
+                space: Space::Occupied,
+                vacuum: Vacuum::Physical
+            })
+        );
     }
 
     #[test]
@@ -1,6 +1,24 @@
+pub mod algebra;
+#[cfg(feature = "serde")]
+pub mod archive;
 pub mod attr;
+pub mod cc;
+pub mod codegen;
+pub mod diagram;
+pub mod error;
+pub mod eval;
 pub mod expr;
+pub mod factorize;
+pub mod format;
 pub mod index;
 pub mod op;
+pub mod ordering;
+pub mod parser;
+pub mod permutation;
+pub mod placeholder;
+pub mod registry;
+pub mod repl;
 pub mod result_expr;
+pub mod tensor;
+pub mod topology;
 pub mod wick;
@@ -1,33 +1,257 @@
+use std::io::{BufRead, Read, Write};
+use std::process::ExitCode;
 use xymbolic::{
+    algebra,
+    cc::CoupledCluster,
+    codegen::{CodeGen, Target},
+    format::Format,
     index::Index,
-    op::{fannx, fcrex},
-    wick::WickTheorem,
+    parser::{parse, parse_term},
+    repl::Session,
+    result_expr::ResultExpr,
 };
 
-#[allow(unused_variables)]
-fn main() {
-    let p1 = Index::new("p_1").build().unwrap();
-    let p2 = Index::new("p_2").build().unwrap();
-    let p3 = Index::new("p_3").build().unwrap();
-    let p4 = Index::new("p_4").build().unwrap();
-
-    let cp1 = fcrex(p1);
-    let cp2 = fcrex(p2);
-    let ap3 = fannx(p3);
-    let ap4 = fannx(p4);
-    let expr = 1.0 * ap3 * ap4 * cp1 * cp2;
-    println!("{}", expr.to_latex());
-
-    let fc = WickTheorem::new(expr.clone())
-        .full_contractions(true)
-        .compute()
-        .to_latex();
-
-    println!("{}", fc);
-
-    let ufc = WickTheorem::new(expr)
-        .full_contractions(false)
-        .compute()
-        .to_latex();
-    println!("{}", ufc);
+const USAGE: &str = "\
+usage: xymbolic <command> [options] [FILE]
+
+commands:
+  wick        normal order an expression
+  vev         vacuum expectation value of an expression
+  commutator  [A, B] of two expressions, one per line
+  cc          coupled-cluster energy and residuals
+  repl        interactive session, FILE is run first as a script
+
+options:
+  -e, --expr EXPR      read the expression from EXPR instead of FILE or stdin
+  -f, --format FORMAT  latex, text, unicode, json or einsum (default text)
+  -o, --output I,J     free indices of the einsum output
+  -r, --rank N         cc truncation, 2 for CCSD (default 2)
+  -h, --help           print this message
+
+Expressions use the text syntax, e.g. \"f[p,q] a+[p] a[q] + 1/4 g[p,q,r,s] a+[p] a+[q] a[s] a[r]\".";
+
+/// Output format of the command line, Format plus the machine readable ones
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Format(Format),
+    Json,
+    Einsum,
+}
+
+struct Args {
+    command: String,
+    expr: Option<String>,
+    file: Option<String>,
+    output: Output,
+    indices: Option<String>,
+    rank: usize,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut it = args.iter();
+    let command = it.next().ok_or("missing command")?.clone();
+    let mut res = Args {
+        command,
+        expr: None,
+        file: None,
+        output: Output::Format(Format::Text),
+        indices: None,
+        rank: 2,
+    };
+    while let Some(arg) = it.next() {
+        let mut value = || {
+            it.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "-e" | "--expr" => res.expr = Some(value()?),
+            "-o" | "--output" => res.indices = Some(value()?),
+            "-r" | "--rank" => {
+                let v = value()?;
+                res.rank = v.parse().map_err(|_| format!("invalid rank {}", v))?;
+            }
+            "-f" | "--format" => {
+                res.output = match value()?.as_str() {
+                    "latex" => Output::Format(Format::Latex),
+                    "text" => Output::Format(Format::Text),
+                    "unicode" => Output::Format(Format::Unicode),
+                    "json" => Output::Json,
+                    "einsum" => Output::Einsum,
+                    f => return Err(format!("unknown format {}", f)),
+                }
+            }
+            a if a.starts_with('-') => return Err(format!("unknown option {}", a)),
+            a => {
+                if res.file.replace(a.to_string()).is_some() {
+                    return Err("more than one input file".to_string());
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+impl Args {
+    /// Expression text from --expr, FILE or stdin
+    fn input(&self) -> Result<String, String> {
+        if let Some(e) = &self.expr {
+            return Ok(e.clone());
+        }
+        match &self.file {
+            Some(f) => std::fs::read_to_string(f).map_err(|e| format!("{}: {}", f, e)),
+            None => {
+                let mut s = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut s)
+                    .map_err(|e| e.to_string())?;
+                Ok(s)
+            }
+        }
+    }
+
+    /// Indices of --output, spaces inferred from the names
+    fn output_indices(&self) -> Result<Option<Vec<Index>>, String> {
+        match &self.indices {
+            None => Ok(None),
+            Some(s) if s.trim().is_empty() => Ok(Some(Vec::new())),
+            Some(s) => {
+                let term = parse_term(&format!("x[{}]", s)).map_err(|e| e.to_string())?;
+                Ok(Some(term.tensors[0].indices.clone()))
+            }
+        }
+    }
+}
+
+/// Render one named result
+fn render(
+    name: &str,
+    expr: &ResultExpr,
+    output: Output,
+    indices: Vec<Index>,
+) -> Result<String, String> {
+    match output {
+        Output::Format(f) => Ok(expr.format(f)),
+        Output::Einsum => CodeGen::new(name, indices)
+            .emit(expr, Target::Einsum)
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "serde")]
+        Output::Json => expr.to_json().map_err(|e| e.to_string()),
+        #[cfg(not(feature = "serde"))]
+        Output::Json => Err("json output requires the serde feature".to_string()),
+    }
+}
+
+/// Output of the command, None when it printed everything itself
+fn run(args: &Args) -> Result<Option<String>, String> {
+    let result = match args.command.as_str() {
+        "wick" => algebra::normal_order(&parse(&args.input()?).map_err(|e| e.to_string())?),
+        "vev" => algebra::vev(&parse(&args.input()?).map_err(|e| e.to_string())?),
+        "commutator" => {
+            let input = args.input()?;
+            let lines: Vec<&str> = input.lines().filter(|l| !l.trim().is_empty()).collect();
+            if lines.len() != 2 {
+                return Err("commutator expects two expressions, one per line".to_string());
+            }
+            let a = parse(lines[0]).map_err(|e| e.to_string())?;
+            let b = parse(lines[1]).map_err(|e| e.to_string())?;
+            algebra::commutator(&a, &b)
+        }
+        "cc" => return run_cc(args).map(Some),
+        "repl" => return run_repl(args),
+        c => return Err(format!("unknown command {}", c)),
+    }
+    .map_err(|e| e.to_string())?;
+    let indices = args.output_indices()?.unwrap_or_default();
+    let body = render("r", &result, args.output, indices)?;
+    Ok(Some(match args.output {
+        Output::Einsum => Target::Einsum.prelude().to_string() + &body,
+        _ => body,
+    }))
+}
+
+/// Energy and every residual up to the truncation rank
+fn run_cc(args: &Args) -> Result<String, String> {
+    let cc = CoupledCluster::new(args.rank);
+    let mut out = Vec::new();
+    for m in 0..=args.rank {
+        let (name, expr) = match m {
+            0 => ("e".to_string(), cc.energy()),
+            _ => (format!("r{}", m), cc.residual(m)),
+        };
+        let indices = CoupledCluster::external_indices(m);
+        let body = render(&name, &expr, args.output, indices)?;
+        out.push(match args.output {
+            Output::Format(Format::Latex) => format!("{} &= {}", name.to_uppercase(), body),
+            Output::Format(_) => format!("{} = {}", name.to_uppercase(), body),
+            Output::Json => format!("\"{}\": {}", name, body),
+            Output::Einsum => body,
+        });
+    }
+    Ok(match args.output {
+        Output::Format(Format::Latex) => {
+            format!(
+                "\\begin{{aligned}}\n{}\n\\end{{aligned}}",
+                out.join(" \\\\\n")
+            )
+        }
+        Output::Json => format!("{{\n{}\n}}", out.join(",\n")),
+        Output::Einsum => Target::Einsum.prelude().to_string() + &out.join("\n\n"),
+        _ => out.join("\n"),
+    })
+}
+
+/// Read statements from stdin until end of input, `help` lists them
+fn run_repl(args: &Args) -> Result<Option<String>, String> {
+    let mut session = Session::new();
+    if let Some(f) = &args.file {
+        let script = std::fs::read_to_string(f).map_err(|e| format!("{}: {}", f, e))?;
+        for line in session.run_script(&script).map_err(|e| e.to_string())? {
+            println!("{}", line);
+        }
+    }
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().map_err(|e| e.to_string())?;
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            // End the prompt line
+            println!();
+            return Ok(None);
+        }
+        if matches!(line.trim(), "quit" | "exit") {
+            return Ok(None);
+        }
+        match session.eval_line(&line) {
+            Ok(s) if s.is_empty() => {}
+            Ok(s) => println!("{}", s),
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match parse_args(&args).and_then(|a| run(&a)) {
+        Ok(out) => {
+            if let Some(s) = out {
+                println!("{}", s);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
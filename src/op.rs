@@ -1,5 +1,6 @@
 /// src/op.rs
 use crate::attr::{Action, Space, Statistics, Vacuum};
+use crate::format::latex_index;
 use crate::index::Index;
 use std::fmt;
 
@@ -10,6 +11,7 @@ const ADJOINT_LABEL: &str = "⁺";
 /// We need to distinguish the space, orbitals, and vacuum
 /// But we can change it, since Index have it
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Op {
     pub index: Index,
     pub action: Action,
@@ -23,7 +25,7 @@ impl Op {
     /// a^+ <-> a
     pub fn dagger(&self) -> Self {
         Self {
-            index: self.index.clone(),
+            index: self.index,
             action: self.action.adjoint(),
         }
     }
@@ -31,25 +33,25 @@ impl Op {
     /// Clean the index name, remove all non-alphanumeric characters
     pub fn alphanumeric_index(&self) -> String {
         self.index
-            .name
+            .name()
             .chars()
             .filter(|c| c.is_alphanumeric())
             .collect()
     }
 
-    /// Latex representation of the operator
+    /// Latex representation of the operator, \hat a^\dagger_{p_1}
     pub fn to_latex(&self, stats: Statistics) -> String {
-        let idx = self.alphanumeric_index();
-        let elem = match stats {
-            Statistics::FermiDirac => "a",
-            _ => "b",
-        };
-        let script = if self.action == Action::Create {
-            "^"
+        let dagger = if self.action == Action::Create {
+            "^\\dagger"
         } else {
-            "_"
+            ""
         };
-        format!("{elem}{}{{{}}}", script, idx)
+        format!(
+            "\\hat {}{}_{{{}}}",
+            stats.symbol(),
+            dagger,
+            latex_index(self.name())
+        )
     }
 
     /// Some interface
@@ -79,9 +81,27 @@ pub fn fannx(index: Index) -> Op {
     Op::new(index, Action::Annihilate)
 }
 
+/// Quasi-annihilator with respect to the vacuum of its index
+/// Physical vacuum: a_p
+/// Fermi vacuum: a^+_i for holes (occupied), a_a for particles (virtual)
+pub fn is_quasi_annihilator(op: &Op) -> bool {
+    if op.vacuum() == Vacuum::Fermi && op.space().is_hole() {
+        op.action == Action::Create
+    } else {
+        op.action == Action::Annihilate
+    }
+}
+
+/// op1 op2 is out of normal order: a quasi-annihilator left of a quasi-creator
+pub fn is_out_of_order(op1: &Op, op2: &Op) -> bool {
+    is_quasi_annihilator(op1) && !is_quasi_annihilator(op2)
+}
+
 /// Can constract or not
+/// The contraction of op1 op2 is non-zero only if they are out of normal order
+/// and live in overlapping spaces
 pub fn can_contract(op1: &Op, op2: &Op) -> bool {
-    matches!(op1.action, Action::Annihilate) && matches!(op2.action, Action::Create)
+    is_out_of_order(op1, op2) && !op1.space().is_disjoint(op2.space())
 }
 
 impl fmt::Display for Op {
@@ -94,7 +114,7 @@ impl fmt::Display for Op {
         write!(
             f,
             "{}{} [Vacuum: {}, Space: {}, Action: {:?}]",
-            self.index.name,
+            self.index.name(),
             label,
             self.index.vacuum(),
             self.index.space(),
@@ -104,43 +124,41 @@ impl fmt::Display for Op {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Delta {
     pub a: Index,
     pub b: Index,
 }
 
 impl Delta {
+    /// Delta of two general indices, always legal on the physical vacuum
     pub fn new(a: String, b: String) -> Self {
         Self {
-            a: Index::new(a).build().unwrap(),
-            b: Index::new(b).build().unwrap(),
+            a: Index::new(a),
+            b: Index::new(b),
         }
     }
     pub fn to_latex(&self) -> String {
         if self.a == self.b {
             return String::new();
         }
-        let idxa: String = self
-            .a
-            .name
-            .chars()
-            .filter(|c| c.is_alphanumeric())
-            .collect();
-        let idxb: String = self
-            .b
-            .name
-            .chars()
-            .filter(|c| c.is_alphanumeric())
-            .collect();
-        format!("s^{{{}}}_{{{}}}", idxa, idxb)
+        format!(
+            "\\delta_{{{}{}}}",
+            latex_index(self.a.name()),
+            latex_index(self.b.name())
+        )
+    }
+    /// Indices of disjoint spaces never coincide
+    pub fn is_zero(&self) -> bool {
+        self.a.space().is_disjoint(self.b.space())
     }
     /// Return the canonical form of the delta operator
-    /// (a, b) if a < b, otherwise (b, a)
-    pub fn canonical(&self) -> (&String, &String) {
-        if self.a.name < self.b.name {
-            (&self.a.name, &self.b.name)
+    /// (a, b) if a < b, otherwise (b, a), spaces and vacua included
+    pub fn canonical(&self) -> (Index, Index) {
+        if self.a < self.b {
+            (self.a, self.b)
         } else {
-            (&self.b.name, &self.a.name)
+            (self.b, self.a)
         }
     }
 }
@@ -160,14 +178,17 @@ mod tests {
     fn test_fcrex() {
         let a = Index::new("a_1").build().unwrap();
         let cp1 = fcrex(a);
-        assert_eq!(cp1.to_latex(Statistics::FermiDirac), "a^{a1}");
+        assert_eq!(
+            cp1.to_latex(Statistics::FermiDirac),
+            "\\hat a^\\dagger_{a_1}"
+        );
     }
 
     #[test]
     fn test_fannx() {
         let a = Index::new("a_1").build().unwrap();
         let ap1 = fannx(a);
-        assert_eq!(ap1.to_latex(Statistics::FermiDirac), "a_{a1}");
+        assert_eq!(ap1.to_latex(Statistics::FermiDirac), "\\hat a_{a_1}");
     }
 
     #[test]
@@ -175,15 +196,36 @@ mod tests {
         let a = Index::new("a_1").build().unwrap();
         let b = Index::new("b_2").build().unwrap();
         let delta = Delta { a, b };
-        assert_eq!(delta.to_latex(), "s^{a1}_{b2}");
+        assert_eq!(delta.to_latex(), "\\delta_{a_1b_2}");
     }
 
     #[test]
     fn test_can_contract() {
         let a = Index::new("a_1").build().unwrap();
-        let cp1 = fcrex(a.clone());
+        let cp1 = fcrex(a);
         let ap1 = fannx(a);
         assert!(can_contract(&ap1, &cp1));
         assert!(!can_contract(&cp1, &ap1));
     }
+
+    #[test]
+    fn test_can_contract_fermi() {
+        let i = Index::new("i")
+            .with_space(Space::Occupied)
+            .with_vacuum(Vacuum::Fermi)
+            .build()
+            .unwrap();
+        let a = Index::new("a")
+            .with_space(Space::Virtual)
+            .with_vacuum(Vacuum::Fermi)
+            .build()
+            .unwrap();
+        // Holes contract as a^+_i a_j, particles as a_a a^+_b
+        assert!(can_contract(&fcrex(i), &fannx(i)));
+        assert!(!can_contract(&fannx(i), &fcrex(i)));
+        assert!(can_contract(&fannx(a), &fcrex(a)));
+        // Out of order, but a hole never contracts with a particle
+        assert!(is_out_of_order(&fcrex(i), &fcrex(a)));
+        assert!(!can_contract(&fcrex(i), &fcrex(a)));
+    }
 }
@@ -0,0 +1,159 @@
+/// src/ordering.rs
+use crate::attr::Action;
+use crate::error::Error;
+use crate::expr::Expr;
+use crate::op::{Delta, Op};
+use crate::result_expr::ResultExpr;
+
+/// How the bosonic operator string of a term is to be read
+/// Normal: :b+ b:, creators left
+/// AntiNormal: annihilators left
+/// Weyl: symmetric, the average over all orderings of the string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OperatorOrder {
+    Normal,
+    AntiNormal,
+    Weyl,
+}
+
+impl OperatorOrder {
+    /// Ordering parameter s of Cahill and Glauber
+    fn s(self) -> f64 {
+        match self {
+            Self::Normal => 1.0,
+            Self::AntiNormal => -1.0,
+            Self::Weyl => 0.0,
+        }
+    }
+
+    /// Sort key of an operator, Weyl strings are written like normal ones
+    fn key(self, o: &Op) -> (bool, &'static str, u16) {
+        let late = match self {
+            Self::AntiNormal => Action::Create,
+            _ => Action::Annihilate,
+        };
+        (o.action == late, o.index.name(), o.space().id())
+    }
+}
+
+impl Expr {
+    /// The s-ordered product of the operators as a sum of t-ordered ones,
+    /// {X}_s = sum over pairings of creators with annihilators of
+    /// ((t - s)/2)^k delta.. {X without the k pairs}_t.
+    /// So b b+ in anti-normal order is b+ b + 1 in normal order and
+    /// b+ b in normal order is b+ b - 1/2 in Weyl order. The operators of
+    /// every result term are sorted into the order `to`.
+    pub fn reorder(&self, from: OperatorOrder, to: OperatorOrder) -> Result<ResultExpr, Error> {
+        if let Some(p) = self.placeholders().first() {
+            return Err(Error::UnexpandedPlaceholder {
+                name: p.name.clone(),
+            });
+        }
+        if !self.ops.is_empty() && !self.is_bose() {
+            return Err(Error::UnsupportedStatistics {
+                statistic: self.statistic,
+                operation: "Reordering",
+            });
+        }
+        let factor = (to.s() - from.s()) / 2.0;
+        let (creators, annihilators): (Vec<usize>, Vec<usize>) =
+            (0..self.ops.len()).partition(|&k| self.ops[k].action == Action::Create);
+        let pairings = if factor == 0.0 {
+            vec![Vec::new()]
+        } else {
+            pairings(&self.ops, &creators, &annihilators)
+        };
+
+        let mut res = ResultExpr::new();
+        for pairs in pairings {
+            let mut e = self.clone();
+            e.ops = (0..self.ops.len())
+                .filter(|k| !pairs.iter().any(|&(c, a)| *k == c || *k == a))
+                .map(|k| self.ops[k].clone())
+                .collect();
+            e.ops.sort_by_key(|o| to.key(o));
+            for &(c, a) in &pairs {
+                e.add_delta(Delta {
+                    a: self.ops[a].index,
+                    b: self.ops[c].index,
+                });
+            }
+            e.coeff *= factor.powi(pairs.len() as i32);
+            res.push_and_merge(e);
+        }
+        Ok(res)
+    }
+}
+
+impl ResultExpr {
+    /// `Expr::reorder` on every term
+    pub fn reorder(&self, from: OperatorOrder, to: OperatorOrder) -> Result<ResultExpr, Error> {
+        let mut res = ResultExpr::new();
+        for t in self.terms() {
+            for e in t.reorder(from, to)? {
+                res.push_and_merge(e);
+            }
+        }
+        res.simplify();
+        Ok(res)
+    }
+}
+
+/// Every set of disjoint (creator, annihilator) pairs in overlapping spaces
+fn pairings(ops: &[Op], creators: &[usize], annihilators: &[usize]) -> Vec<Vec<(usize, usize)>> {
+    let Some((&c, rest)) = creators.split_first() else {
+        return vec![Vec::new()];
+    };
+    let mut res = pairings(ops, rest, annihilators);
+    for (k, &a) in annihilators.iter().enumerate() {
+        if ops[c].space().is_disjoint(ops[a].space()) {
+            continue;
+        }
+        let mut left = annihilators.to_vec();
+        left.remove(k);
+        for mut pairs in pairings(ops, rest, &left) {
+            pairs.insert(0, (c, a));
+            res.push(pairs);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OperatorOrder::*;
+    use crate::error::Error;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_reorder() {
+        let r = parse("b[p] b+[q]").unwrap();
+        let normal = r.reorder(AntiNormal, Normal).unwrap();
+        assert_eq!(normal.to_text(), "b+[q] b[p] + delta[p,q]");
+        assert_eq!(
+            normal.reorder(Normal, AntiNormal).unwrap().to_text(),
+            "b[p] b+[q]"
+        );
+
+        // b+ b = {b+ b}_W - 1/2, and back
+        let r = parse("w[p,q] b+[p] b[q]").unwrap();
+        let weyl = r.reorder(Normal, Weyl).unwrap();
+        assert_eq!(
+            weyl.to_text(),
+            "w[p,q] b+[p] b[q] - 0.5 * delta[q,p] w[p,q]"
+        );
+        assert_eq!(weyl.reorder(Weyl, Normal).unwrap().to_text(), r.to_text());
+
+        // b b b+ b+ = b+ b+ b b + 4 b+ b + 2 for a single mode
+        let r = parse("b[p] b[p] b+[p] b+[p]").unwrap();
+        let normal = r.reorder(AntiNormal, Normal).unwrap();
+        let coeffs: Vec<f64> = normal.terms().iter().map(|t| t.coeff).collect();
+        assert_eq!(coeffs, [1.0, 4.0, 2.0]);
+
+        assert!(matches!(
+            parse("a+[p] a[q]").unwrap().reorder(Normal, Weyl),
+            Err(Error::UnsupportedStatistics { .. })
+        ));
+    }
+}
@@ -0,0 +1,608 @@
+/// src/parser.rs
+use crate::attr::{Action, Occupancy, Space, Statistics, Vacuum};
+use crate::expr::Expr;
+use crate::index::Index;
+use crate::op::{Delta, Op};
+use crate::permutation::Permutation;
+use crate::result_expr::ResultExpr;
+use crate::tensor::Tensor;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Parse error with the character offset where it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(pos: usize, message: impl Into<String>) -> Self {
+        Self {
+            pos,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Parse error at {}: {}", self.pos, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Space of an index from the first letter of its name
+/// i, j, k, l, m, n, o: Occupied
+/// a, b, c, d, e, f, g, h: Virtual
+/// anything else (p, q, r, s, ...): General
+pub fn infer_space(name: &str) -> Space {
+    match name.chars().next() {
+        Some('i'..='o') => Space::Occupied,
+        Some('a'..='h') => Space::Virtual,
+        _ => Space::General,
+    }
+}
+
+/// Parse a sum of terms such as
+/// `0.5 * f[p,q] a+[p] a[q] - 1/4 g[pq,rs] a+[p] a+[q] a[s] a[r]`
+/// Factors of a term:
+/// a+[p] / a[p]: fermionic creation / annihilation
+/// b+[p] / b[p]: bosonic creation / annihilation
+/// c+[p] / c[p]: creation / annihilation of arbitrary statistics
+/// delta[p,q]: Kronecker delta
+/// P(i,j|a,b): permutation operator, see `Permutation`
+/// H: a bare label is a named operator, see `Placeholder`
+/// label[...]: tensor, commas only group indices, `pq` reads as p, q
+/// Indices are a letter followed by optional digits, p1 or p_1.
+/// A suffix :o, :v, :g or :c overrides the inferred space, x:o, as does
+/// the name of a space from `Space::define`, x:active.
+/// Any index of an active space puts the whole input on the multireference
+/// vacuum, any other occupied or virtual index on the Fermi vacuum,
+/// otherwise it lives on the physical vacuum. A leading @physical,
+/// @fermi or @mr sets the vacuum instead.
+pub fn parse(input: &str) -> Result<ResultExpr, ParseError> {
+    parse_with_spaces(input, &HashMap::new())
+}
+
+/// Parse with declared spaces, names in `spaces` skip the inference
+pub fn parse_with_spaces(
+    input: &str,
+    spaces: &HashMap<String, Space>,
+) -> Result<ResultExpr, ParseError> {
+    let mut parser = Parser::new(input, spaces);
+    let vacuum = parser.parse_vacuum()?;
+    let raw = parser.parse_sum()?;
+
+    let vacuum = vacuum
+        .unwrap_or_else(|| infer_vacuum(raw.iter().flat_map(|t| &t.indices).map(|i| i.space)));
+    raw.into_iter().map(|t| t.build(vacuum)).collect()
+}
+
+/// Vacuum the parser picks for indices of these spaces
+pub fn infer_vacuum(spaces: impl IntoIterator<Item = Space>) -> Vacuum {
+    let mut vacuum = Vacuum::Physical;
+    for s in spaces {
+        if s.occupancy() == Some(Occupancy::Active) {
+            return Vacuum::MultiReference;
+        }
+        if s != Space::General {
+            vacuum = Vacuum::Fermi;
+        }
+    }
+    vacuum
+}
+
+/// Name of the vacuum in the `@fermi` prefix
+pub fn vacuum_tag(vacuum: Vacuum) -> &'static str {
+    match vacuum {
+        Vacuum::Physical => "physical",
+        Vacuum::Fermi => "fermi",
+        Vacuum::MultiReference => "mr",
+    }
+}
+
+/// Parse a single term, e.g. `-2 t[i,a] a+[a] a[i]`
+pub fn parse_term(input: &str) -> Result<Expr, ParseError> {
+    let res = parse(input)?;
+    match res.len() {
+        0 => Ok(Expr::new().set_coeff(0.0)),
+        1 => Ok(res.into_iter().next().unwrap()),
+        n => Err(ParseError::new(
+            0,
+            format!("Expected one term, found {}", n),
+        )),
+    }
+}
+
+impl FromStr for ResultExpr {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+/// Factor of a term before the vacuum is known
+enum RawFactor {
+    Op(usize, Action),
+    Tensor(String, Vec<usize>),
+    Delta(usize, usize),
+    Perm(Vec<(usize, usize)>),
+    Placeholder(String),
+}
+
+/// Index of a term with the position of its first use
+struct RawIndex {
+    name: String,
+    space: Space,
+    pos: usize,
+}
+
+struct RawTerm {
+    coeff: f64,
+    statistic: Option<Statistics>,
+    indices: Vec<RawIndex>,
+    factors: Vec<RawFactor>,
+}
+
+impl RawTerm {
+    fn index(&mut self, name: String, space: Space, pos: usize) -> Result<usize, ParseError> {
+        match self.indices.iter().position(|i| i.name == name) {
+            Some(k) if self.indices[k].space != space => Err(ParseError::new(
+                pos,
+                format!(
+                    "Index {} used with spaces {} and {}",
+                    name, self.indices[k].space, space
+                ),
+            )),
+            Some(k) => Ok(k),
+            None => {
+                self.indices.push(RawIndex { name, space, pos });
+                Ok(self.indices.len() - 1)
+            }
+        }
+    }
+
+    fn build(self, vacuum: Vacuum) -> Result<Expr, ParseError> {
+        let indices = self
+            .indices
+            .iter()
+            .map(|i| {
+                Index::new(i.name.clone())
+                    .with_space(i.space)
+                    .with_vacuum(vacuum)
+                    .build()
+                    .map_err(|e| ParseError::new(i.pos, e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut e = Expr::new()
+            .set_coeff(self.coeff)
+            .set_statistic(self.statistic.unwrap_or(Statistics::FermiDirac));
+        for f in self.factors {
+            match f {
+                RawFactor::Op(k, action) => e.append_op(Op::new(indices[k], action)),
+                RawFactor::Tensor(label, ks) => {
+                    e.append_tensor(Tensor::new(label, ks.iter().map(|&k| indices[k]).collect()))
+                }
+                // Kept as written, merging would drop the index they share
+                RawFactor::Delta(a, b) if a != b => e.deltas.push(Delta {
+                    a: indices[a],
+                    b: indices[b],
+                }),
+                RawFactor::Delta(..) => {}
+                RawFactor::Perm(pairs) => e.perms.push(Permutation::new(
+                    pairs
+                        .iter()
+                        .map(|&(a, b)| (indices[a], indices[b]))
+                        .collect(),
+                )),
+                RawFactor::Placeholder(name) => e.append_placeholder(name),
+            }
+        }
+        Ok(e)
+    }
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    spaces: &'a HashMap<String, Space>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &str, spaces: &'a HashMap<String, Space>) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos: 0,
+            spaces,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, k: usize) -> Option<char> {
+        self.chars.get(self.pos + k).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(c) => ParseError::new(self.pos, format!("Expected {}, found '{}'", expected, c)),
+            None => ParseError::new(
+                self.pos,
+                format!("Expected {}, found end of input", expected),
+            ),
+        }
+    }
+
+    /// Optional @physical, @fermi or @mr in front of the sum
+    fn parse_vacuum(&mut self) -> Result<Option<Vacuum>, ParseError> {
+        self.skip_ws();
+        if self.peek() != Some('@') {
+            return Ok(None);
+        }
+        let start = self.pos;
+        self.pos += 1;
+        let mut name = String::new();
+        while self.peek().is_some_and(|c| c.is_alphanumeric()) {
+            name.push(self.peek().unwrap());
+            self.pos += 1;
+        }
+        [Vacuum::Physical, Vacuum::Fermi, Vacuum::MultiReference]
+            .into_iter()
+            .find(|&v| vacuum_tag(v) == name)
+            .map(Some)
+            .ok_or_else(|| ParseError::new(start, format!("Unknown vacuum '@{}'", name)))
+    }
+
+    fn parse_sum(&mut self) -> Result<Vec<RawTerm>, ParseError> {
+        let mut terms = Vec::new();
+        self.skip_ws();
+        if self.peek().is_none() {
+            return Err(self.unexpected("a term"));
+        }
+        let mut sign = 1.0;
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some('+') => self.pos += 1,
+                Some('-') => {
+                    self.pos += 1;
+                    sign = -sign;
+                }
+                _ => {}
+            }
+            let mut term = self.parse_term()?;
+            term.coeff *= sign;
+            terms.push(term);
+
+            self.skip_ws();
+            match self.peek() {
+                None => return Ok(terms),
+                Some('+') => sign = 1.0,
+                Some('-') => sign = -1.0,
+                Some(_) => return Err(self.unexpected("'+', '-' or end of input")),
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<RawTerm, ParseError> {
+        let mut term = RawTerm {
+            coeff: 1.0,
+            statistic: None,
+            indices: Vec::new(),
+            factors: Vec::new(),
+        };
+
+        self.skip_ws();
+        let mut empty = true;
+        if self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            term.coeff = self.parse_coeff()?;
+            empty = false;
+            self.skip_ws();
+            if self.peek() == Some('*') {
+                self.pos += 1;
+                self.skip_ws();
+                if !self.peek().is_some_and(|c| c.is_alphabetic()) {
+                    return Err(self.unexpected("a factor"));
+                }
+            }
+        }
+
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some(c) if c.is_alphabetic() => {
+                    self.parse_factor(&mut term)?;
+                    empty = false;
+                }
+                Some('*') if !empty => {
+                    self.pos += 1;
+                    self.skip_ws();
+                    if !self.peek().is_some_and(|c| c.is_alphabetic()) {
+                        return Err(self.unexpected("a factor"));
+                    }
+                }
+                _ => break,
+            }
+        }
+        if empty {
+            return Err(self.unexpected("a term"));
+        }
+        Ok(term)
+    }
+
+    /// number, or number / number
+    fn parse_coeff(&mut self) -> Result<f64, ParseError> {
+        let num = self.parse_number()?;
+        self.skip_ws();
+        if self.peek() == Some('/') {
+            self.pos += 1;
+            self.skip_ws();
+            let pos = self.pos;
+            let den = self.parse_number()?;
+            if den == 0.0 {
+                return Err(ParseError::new(pos, "Division by zero"));
+            }
+            return Ok(num / den);
+        }
+        Ok(num)
+    }
+
+    fn parse_number(&mut self) -> Result<f64, ParseError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        // Exponent, 1e-3
+        if self.peek().is_some_and(|c| c == 'e' || c == 'E')
+            && self
+                .peek_at(1)
+                .is_some_and(|c| c.is_ascii_digit() || c == '-' || c == '+')
+        {
+            self.pos += 2;
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse::<f64>()
+            .map_err(|_| ParseError::new(start, format!("Invalid number '{}'", text)))
+    }
+
+    fn parse_factor(&mut self, term: &mut RawTerm) -> Result<(), ParseError> {
+        let start = self.pos;
+        let mut label = String::new();
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            label.push(self.peek().unwrap());
+            self.pos += 1;
+        }
+
+        // a+[p], a[p], b+[p], b[p], c+[p], c[p]
+        if label == "a" || label == "b" || label == "c" {
+            let action = if self.peek() == Some('+') && self.peek_at(1) == Some('[') {
+                self.pos += 1;
+                Action::Create
+            } else {
+                Action::Annihilate
+            };
+            let statistic = match label.as_str() {
+                "a" => Statistics::FermiDirac,
+                "b" => Statistics::BoseEinstein,
+                _ => Statistics::Arbitrary,
+            };
+            if term.statistic.is_some_and(|s| s != statistic) {
+                return Err(ParseError::new(
+                    start,
+                    "Operators of different statistics in the same term",
+                ));
+            }
+            term.statistic = Some(statistic);
+
+            let indices = self.parse_indices(term)?;
+            if indices.len() != 1 {
+                return Err(ParseError::new(
+                    start,
+                    format!("Operator takes one index, found {}", indices.len()),
+                ));
+            }
+            term.factors.push(RawFactor::Op(indices[0], action));
+            return Ok(());
+        }
+
+        // P(i,j|a,b): permutation operator
+        if label == "P" && self.peek() == Some('(') {
+            let pairs = self.parse_pairs(term)?;
+            term.factors.push(RawFactor::Perm(pairs));
+            return Ok(());
+        }
+
+        // H, T1: named operator without indices
+        if label != "delta" && self.peek() != Some('[') {
+            term.factors.push(RawFactor::Placeholder(label));
+            return Ok(());
+        }
+
+        let indices = self.parse_indices(term)?;
+        if label == "delta" {
+            if indices.len() != 2 {
+                return Err(ParseError::new(
+                    start,
+                    format!("delta takes two indices, found {}", indices.len()),
+                ));
+            }
+            term.factors.push(RawFactor::Delta(indices[0], indices[1]));
+        } else {
+            term.factors.push(RawFactor::Tensor(label, indices));
+        }
+        Ok(())
+    }
+
+    /// [p,q] or [pq,rs]: a bracketed list of index names
+    fn parse_indices(&mut self, term: &mut RawTerm) -> Result<Vec<usize>, ParseError> {
+        if self.peek() != Some('[') {
+            return Err(self.unexpected("'['"));
+        }
+        self.pos += 1;
+        let mut groups = self.parse_groups(term, ']')?;
+        match groups.len() {
+            1 => Ok(groups.pop().unwrap()),
+            _ => Err(ParseError::new(
+                self.pos,
+                "'|' only separates permutation pairs",
+            )),
+        }
+    }
+
+    /// (i,j|a,b): index pairs of a permutation operator
+    fn parse_pairs(&mut self, term: &mut RawTerm) -> Result<Vec<(usize, usize)>, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        self.parse_groups(term, ')')?
+            .into_iter()
+            .map(|g| match g[..] {
+                [a, b] => Ok((a, b)),
+                _ => Err(ParseError::new(
+                    start,
+                    format!("P takes pairs of indices, found {}", g.len()),
+                )),
+            })
+            .collect()
+    }
+
+    /// Index names up to `close`, '|' starts a new group
+    fn parse_groups(
+        &mut self,
+        term: &mut RawTerm,
+        close: char,
+    ) -> Result<Vec<Vec<usize>>, ParseError> {
+        let mut groups = Vec::new();
+        let mut indices = Vec::new();
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    groups.push(indices);
+                    return Ok(groups);
+                }
+                Some('|') if !indices.is_empty() => {
+                    self.pos += 1;
+                    groups.push(std::mem::take(&mut indices));
+                }
+                Some(',') if !indices.is_empty() => self.pos += 1,
+                Some(c) if c.is_alphabetic() => {
+                    let pos = self.pos;
+                    let mut name = c.to_string();
+                    self.pos += 1;
+                    if self.peek() == Some('_') {
+                        name.push('_');
+                        self.pos += 1;
+                        if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                            return Err(self.unexpected("a digit"));
+                        }
+                    }
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        name.push(self.peek().unwrap());
+                        self.pos += 1;
+                    }
+                    let space = if self.peek() == Some(':') {
+                        self.pos += 1;
+                        // Intersections of spaces are registered as a&b
+                        let word: String = self.chars[self.pos..]
+                            .iter()
+                            .take_while(|c| c.is_alphanumeric() || **c == '_' || **c == '&')
+                            .collect();
+                        // A one letter word is always a tag, x:oy:v reads as x:o y:v
+                        let (space, len) = match Space::lookup(&word).filter(|_| word.len() > 1) {
+                            Some(space) => (space, word.chars().count()),
+                            None => match self.peek() {
+                                Some('o') => (Space::Occupied, 1),
+                                Some('v') => (Space::Virtual, 1),
+                                Some('g') => (Space::General, 1),
+                                Some('c') => (Space::DoublyOccupied, 1),
+                                _ => {
+                                    return Err(
+                                        self.unexpected("one of o, v, g, c or a space name")
+                                    );
+                                }
+                            },
+                        };
+                        self.pos += len;
+                        space
+                    } else {
+                        match self.spaces.get(&name) {
+                            Some(&space) => space,
+                            None => infer_space(&name),
+                        }
+                    };
+                    indices.push(term.index(name, space, pos)?);
+                }
+                _ => return Err(self.unexpected(&format!("an index or '{}'", close))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hamiltonian() {
+        let h = parse("0.5 * f[p,q] a+[p] a[q] - 1/4 g[pq,rs] a+[p] a+[q] a[s] a[r]").unwrap();
+        assert_eq!(h.terms().len(), 2);
+        assert_eq!(h.terms()[0].coeff, 0.5);
+        assert_eq!(h.terms()[1].coeff, -0.25);
+        assert_eq!(h.terms()[1].tensors[0].indices.len(), 4);
+        assert_eq!(h.terms()[1].ops[3].name(), "r");
+        assert_eq!(h.terms()[1].ops[3].vacuum(), Vacuum::Physical);
+        assert_eq!(
+            h.to_text(),
+            "0.5 * f[p,q] a+[p] a[q] - 0.25 * g[p,q,r,s] a+[p] a+[q] a[s] a[r]"
+        );
+    }
+
+    #[test]
+    fn test_parse_spaces() {
+        let t = parse_term("-t[i_1,a_1] delta[i_1,j] b+[a_1] b[j]").unwrap();
+        assert_eq!(t.coeff, -1.0);
+        assert!(t.is_bose());
+        assert_eq!(t.ops[0].space(), Space::Virtual);
+        assert_eq!(t.ops[1].space(), Space::Occupied);
+        assert_eq!(t.ops[1].vacuum(), Vacuum::Fermi);
+        assert_eq!(t.deltas.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_shared_deltas() {
+        let t = parse_term("delta[p,q] delta[r,p]").unwrap();
+        let names: Vec<(&str, &str)> = t.deltas.iter().map(|d| (d.a.name(), d.b.name())).collect();
+        assert_eq!(names, vec![("p", "q"), ("r", "p")]);
+        assert_eq!(t.to_text(), "delta[p,q] delta[r,p]");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse("f[p,q] a+[p] +").unwrap_err();
+        assert_eq!(err.pos, 14);
+        let err = parse("f[p,q a[q]").unwrap_err();
+        assert_eq!(err.pos, 7);
+        // General index on the Fermi vacuum
+        let err = parse("f[p,i]").unwrap_err();
+        assert_eq!(err.pos, 2);
+        assert!(parse("a+[p] b[q]").is_err());
+        assert_eq!(parse("t[x:o,x:v]").unwrap_err().pos, 6);
+    }
+}
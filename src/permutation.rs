@@ -0,0 +1,208 @@
+/// src/permutation.rs
+use crate::expr::{Expr, Summation};
+use crate::format::latex_index;
+use crate::index::Index;
+use crate::result_expr::ResultExpr;
+use std::collections::HashMap;
+
+/// Permutation operator over index pairs, P(ij|ab) = P(ij) P(ab)
+/// with P(ij) = 1 - (i <-> j), so P(ij) f_{ij} = f_{ij} - f_{ji}.
+/// It acts on the whole term it multiplies, its indices are free ones.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Permutation {
+    pub pairs: Vec<(Index, Index)>,
+}
+
+impl Permutation {
+    pub fn new(pairs: Vec<(Index, Index)>) -> Self {
+        Self { pairs }
+    }
+
+    /// \hat P(ij|ab)
+    pub fn to_latex(&self) -> String {
+        let pairs: Vec<String> = self
+            .pairs
+            .iter()
+            .map(|(a, b)| format!("{}{}", latex_index(a.name()), latex_index(b.name())))
+            .collect();
+        format!("\\hat P({})", pairs.join("|"))
+    }
+
+    /// Pairs as sorted names, P(ji|ab) and P(ab|ij) give the same key
+    pub fn sort_key(&self) -> Vec<(&'static str, &'static str)> {
+        let mut pairs: Vec<_> = self
+            .pairs
+            .iter()
+            .map(|(a, b)| {
+                let (a, b) = (a.name(), b.name());
+                if a < b { (a, b) } else { (b, a) }
+            })
+            .collect();
+        pairs.sort_unstable();
+        pairs
+    }
+}
+
+impl Expr {
+    /// The term without permutation operators, one image per permutation
+    pub fn expand_permutations(&self) -> ResultExpr {
+        let mut bare = self.clone();
+        let perms = std::mem::take(&mut bare.perms);
+        let mut terms = vec![bare];
+        for &(a, b) in perms.iter().flat_map(|p| &p.pairs) {
+            let images: Vec<Expr> = terms
+                .iter()
+                .map(|t| {
+                    let s = t.swapped(a, b);
+                    let c = -s.coeff;
+                    s.set_coeff(c)
+                })
+                .collect();
+            terms.extend(images);
+        }
+        terms.into_iter().collect()
+    }
+}
+
+impl ResultExpr {
+    pub fn expand_permutations(&self) -> ResultExpr {
+        let mut res: ResultExpr = self
+            .terms()
+            .iter()
+            .flat_map(|t| t.expand_permutations())
+            .collect();
+        res.simplify();
+        res
+    }
+
+    /// Collapse terms related by swapping free indices of the same space
+    /// into one term with a permutation operator: first P(ij|ab) from four
+    /// terms, then P(ij) from two. Terms only match when equal up to the
+    /// swap and the sign, dummies are not renamed to find a match.
+    pub fn collapse_permutations(&self) -> ResultExpr {
+        let mut by_key: HashMap<_, Vec<usize>> = HashMap::new();
+        for (k, t) in self.terms().iter().enumerate() {
+            by_key.entry(t.key()).or_default().push(k);
+        }
+        let mut used = vec![false; self.len()];
+        let find = |used: &[bool], e: &Expr| {
+            by_key.get(&e.key())?.iter().copied().find(|&j| {
+                !used[j] && (self.terms()[j].coeff - e.coeff).abs() < 1e-10 * e.coeff.abs().max(1.0)
+            })
+        };
+
+        let mut res = ResultExpr::new();
+        for (k, t) in self.terms().iter().enumerate() {
+            if used[k] {
+                continue;
+            }
+            used[k] = true;
+            if !t.perms.is_empty() {
+                res.push_and_merge(t.clone());
+                continue;
+            }
+            let free = t.free_indices(&Summation::Einstein);
+            let mut pairs = Vec::new();
+            for (x, &a) in free.iter().enumerate() {
+                for &b in &free[x + 1..] {
+                    if a.space() == b.space() {
+                        pairs.push(if a.name() < b.name() { (a, b) } else { (b, a) });
+                    }
+                }
+            }
+            let image = |e: &Expr, (a, b): (Index, Index)| {
+                let s = e.swapped(a, b);
+                let c = -s.coeff;
+                s.set_coeff(c)
+            };
+            let mut found: Option<(Permutation, Vec<usize>)> = None;
+            'search: for (x, &p) in pairs.iter().enumerate() {
+                for &q in &pairs[x + 1..] {
+                    if [q.0, q.1].iter().any(|i| *i == p.0 || *i == p.1) {
+                        continue;
+                    }
+                    let mut taken = used.clone();
+                    let mut hits = Vec::new();
+                    for e in [image(t, p), image(t, q), image(&image(t, p), q)] {
+                        match find(&taken, &e) {
+                            Some(j) => {
+                                taken[j] = true;
+                                hits.push(j);
+                            }
+                            None => break,
+                        }
+                    }
+                    if hits.len() == 3 {
+                        found = Some((Permutation::new(vec![p, q]), hits));
+                        break 'search;
+                    }
+                }
+            }
+            if found.is_none() {
+                found = pairs.iter().find_map(|&p| {
+                    find(&used, &image(t, p)).map(|j| (Permutation::new(vec![p]), vec![j]))
+                });
+            }
+            let mut term = t.clone();
+            if let Some((perm, hits)) = found {
+                for j in hits {
+                    used[j] = true;
+                }
+                term.perms.push(perm);
+            }
+            res.push_and_merge(term);
+        }
+        res
+    }
+}
+
+/// Every ordering of `items`, the first position varying slowest
+pub(crate) fn permutations(items: &[usize]) -> Vec<Vec<usize>> {
+    if items.len() <= 1 {
+        return vec![items.to_vec()];
+    }
+    let mut out = Vec::new();
+    for k in 0..items.len() {
+        let mut rest = items.to_vec();
+        let first = rest.remove(k);
+        for mut p in permutations(&rest) {
+            p.insert(0, first);
+            out.push(p);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+
+    #[test]
+    fn test_collapse_and_expand() {
+        let r = parse(
+            "f[k,i] t[a,b,k,j] - f[k,j] t[a,b,k,i] \
+             + g[k,b,i,j] t[a,k] - g[k,a,i,j] t[b,k] \
+             + x[a,i] y[b,j] - x[a,j] y[b,i] - x[b,i] y[a,j] + x[b,j] y[a,i] + f[a,b]",
+        )
+        .unwrap();
+        let collapsed = r.collapse_permutations();
+        assert_eq!(
+            collapsed.to_text(),
+            "P(i,j) f[k,i] t[a,b,k,j] + P(a,b) g[k,b,i,j] t[a,k] + P(a,b|i,j) x[a,i] y[b,j] + f[a,b]"
+        );
+        assert_eq!(
+            collapsed.to_latex(),
+            "\\hat P(ij) f_{ki} t_{abkj} + \\hat P(ab) g_{kbij} t_{ak} + \\hat P(ab|ij) x_{ai} y_{bj} + f_{ab}"
+        );
+        let back = parse(&collapsed.to_text()).unwrap().expand_permutations();
+        assert_eq!(back.terms().len(), r.terms().len());
+        for t in r.terms() {
+            assert!(
+                back.terms()
+                    .iter()
+                    .any(|b| b.key() == t.key() && b.coeff == t.coeff)
+            );
+        }
+    }
+}
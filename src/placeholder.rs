@@ -0,0 +1,119 @@
+/// src/placeholder.rs
+use crate::error::Error;
+use crate::expr::Expr;
+use crate::result_expr::ResultExpr;
+
+/// Named composite operator such as H or T, standing in the operator
+/// string before `ops[position]` until `ResultExpr::substitute` expands it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Placeholder {
+    pub name: String,
+    pub position: usize,
+}
+
+impl Placeholder {
+    pub fn new(name: impl Into<String>, position: usize) -> Self {
+        Self {
+            name: name.into(),
+            position,
+        }
+    }
+
+    /// \hat{H}
+    pub fn to_latex(&self) -> String {
+        format!("\\hat{{{}}}", self.name)
+    }
+}
+
+impl Expr {
+    /// A term holding only the placeholder `name`
+    pub fn placeholder(name: impl Into<String>) -> Expr {
+        let mut e = Expr::new();
+        e.append_placeholder(name);
+        e
+    }
+
+    /// Placeholder after the operators appended so far
+    pub fn append_placeholder(&mut self, name: impl Into<String>) {
+        let position = self.ops.len();
+        self.placeholders.push(Placeholder::new(name, position));
+    }
+}
+
+impl ResultExpr {
+    /// Replace every placeholder `name` by `expansion`, distributing the
+    /// products. Dummies of the expansion that clash with indices of the
+    /// term are renamed, as in `Expr::try_mul`. Placeholders brought in by
+    /// the expansion are kept, even when they are called `name` too.
+    pub fn substitute(&self, name: &str, expansion: &ResultExpr) -> Result<ResultExpr, Error> {
+        let mut res = ResultExpr::new();
+        for t in self.terms() {
+            substitute_term(t, name, expansion, t.placeholders.len(), &mut res)?;
+        }
+        res.simplify();
+        Ok(res)
+    }
+}
+
+/// Expand the last placeholder `name` among the first `below` ones, then
+/// the ones before it, so inserted placeholders are never revisited
+fn substitute_term(
+    term: &Expr,
+    name: &str,
+    expansion: &ResultExpr,
+    below: usize,
+    out: &mut ResultExpr,
+) -> Result<(), Error> {
+    match term.placeholders[..below]
+        .iter()
+        .rposition(|p| p.name == name)
+    {
+        None => out.push_and_merge(term.clone()),
+        Some(k) => {
+            for e in expansion.terms() {
+                substitute_term(&term.splice(k, e.clone())?, name, expansion, k, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_substitute() {
+        let r = parse("0.5 * H T T + t[a,i] a+[a] V a[i]").unwrap();
+        assert_eq!(
+            r.to_latex(),
+            "\\frac{1}{2} \\hat{H} \\hat{T} \\hat{T} + t_{ai} \\hat a^\\dagger_{a} \\hat{V} \\hat a_{i}"
+        );
+
+        let t = parse("t[a,i] a+[a] a[i] + 0.25 * t[a,b,i,j] a+[a] a+[b] a[j] a[i]").unwrap();
+        let res = r.substitute("T", &t).unwrap();
+        assert_eq!(res.terms().len(), 5);
+        // Both copies of T get their own dummies
+        assert_eq!(
+            res.terms()[0].to_text(),
+            "0.5 * t[a,i] t[b,j] H a+[b] a[j] a+[a] a[i]"
+        );
+        assert_eq!(res.terms()[1].coeff, 0.125);
+        // V still waits for its expansion, its neighbours keep their place
+        let res = res
+            .substitute("V", &parse("g[a,b] a+[a] a[b]").unwrap())
+            .unwrap();
+        assert_eq!(
+            res.terms()[4].to_text(),
+            "t[a,i] g[c,b] a+[a] a+[c] a[b] a[i]"
+        );
+
+        let bose = parse("w[p] b+[p]").unwrap();
+        assert!(matches!(
+            r.substitute("V", &bose),
+            Err(Error::MismatchedStatistics { .. })
+        ));
+    }
+}
@@ -0,0 +1,300 @@
+/// src/registry.rs
+use crate::attr::{Occupancy, Space};
+use crate::error::Error;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+
+/// Every index name seen so far, leaked on first use so an Index can
+/// carry a 'static borrow and compare names by address. Each distinct name
+/// is leaked once and never freed, the table grows with the number of
+/// distinct names a process uses, not with the number of indices.
+fn names() -> &'static RwLock<HashSet<&'static str>> {
+    static NAMES: OnceLock<RwLock<HashSet<&'static str>>> = OnceLock::new();
+    NAMES.get_or_init(|| RwLock::new(HashSet::new()))
+}
+
+/// The one interned copy of `name`, registering it on first use
+pub fn intern(name: &str) -> &'static str {
+    if let Some(&name) = names().read().unwrap().get(name) {
+        return name;
+    }
+    let mut reg = names().write().unwrap();
+    if let Some(&name) = reg.get(name) {
+        return name;
+    }
+    let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+    reg.insert(name);
+    name
+}
+
+/// A registered space: its name and the atoms it is made of, one bit each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpaceData {
+    pub name: &'static str,
+    pub mask: u64,
+}
+
+const MAX_SPACES: usize = 1 << 12;
+
+/// Slot of a space, written once under the `Spaces` lock and read
+/// without any lock, the mask grows when a space is defined inside it
+struct SpaceSlot {
+    name: OnceLock<&'static str>,
+    mask: AtomicU64,
+}
+
+/// Lattice of spaces over at most 64 disjoint atoms, the position in
+/// `slots` is the id carried by Space. Reads are plain atomic loads, so
+/// the hot Wick loop never takes a lock; `writer` serializes definitions.
+/// Slots are never freed: every definition, union and first intersection
+/// of a new pair of orbital sets takes one, up to MAX_SPACES in total.
+struct Spaces {
+    slots: Box<[SpaceSlot]>,
+    atoms: [OnceLock<Occupancy>; 64],
+    writer: Mutex<Writer>,
+}
+
+/// Bookkeeping only definitions and lookups by name need
+struct Writer {
+    names: HashMap<&'static str, u16>,
+    len: usize,
+    atoms: usize,
+}
+
+impl Spaces {
+    /// Occupied, Virtual and DoublyOccupied are atoms, General is all of them
+    fn builtin() -> Self {
+        let spaces = Self {
+            slots: (0..MAX_SPACES)
+                .map(|_| SpaceSlot {
+                    name: OnceLock::new(),
+                    mask: AtomicU64::new(0),
+                })
+                .collect(),
+            atoms: [const { OnceLock::new() }; 64],
+            writer: Mutex::new(Writer {
+                names: HashMap::new(),
+                len: 0,
+                atoms: 0,
+            }),
+        };
+        let mut w = spaces.writer.lock().unwrap();
+        for occupancy in [Occupancy::Hole, Occupancy::Particle, Occupancy::Hole] {
+            spaces.push_atom(&mut w, occupancy);
+        }
+        for (name, mask) in [
+            ("General", 0b111),
+            ("Occupied", 0b001),
+            ("Virtual", 0b010),
+            ("DoublyOccupied", 0b100),
+        ] {
+            spaces.push(&mut w, name, mask).unwrap();
+        }
+        drop(w);
+        spaces
+    }
+
+    fn push_atom(&self, w: &mut Writer, occupancy: Occupancy) -> u64 {
+        self.atoms[w.atoms].set(occupancy).unwrap();
+        w.atoms += 1;
+        1 << (w.atoms - 1)
+    }
+
+    /// Register a space, its name is leaked unless an earlier space has it
+    fn push(&self, w: &mut Writer, name: &str, mask: u64) -> Result<Space, Error> {
+        if w.len == MAX_SPACES {
+            return Err(Error::InvalidSpace {
+                name: name.to_string(),
+                reason: "the space registry is full",
+            });
+        }
+        let id = w.len as u16;
+        let name: &'static str = match w.names.get_key_value(name) {
+            Some((&name, _)) => name,
+            None => Box::leak(name.to_string().into_boxed_str()),
+        };
+        let slot = &self.slots[w.len];
+        slot.mask.store(mask, Ordering::Release);
+        slot.name.set(name).unwrap();
+        w.names.insert(name, id);
+        w.len += 1;
+        Ok(Space::from_id(id))
+    }
+
+    fn mask(&self, space: Space) -> u64 {
+        self.slots[space.id() as usize].mask.load(Ordering::Acquire)
+    }
+
+    fn check_name(w: &Writer, name: &str) -> Result<(), Error> {
+        let reason = if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            "names are alphanumeric"
+        } else if w.names.contains_key(name) {
+            "the name is taken"
+        } else {
+            return Ok(());
+        };
+        Err(Error::InvalidSpace {
+            name: name.to_string(),
+            reason,
+        })
+    }
+
+    fn occupancy(&self, mask: u64) -> Option<Occupancy> {
+        let mut atoms = (0..64)
+            .filter(|&k| mask & (1 << k) != 0)
+            .map(|k| *self.atoms[k].get().unwrap());
+        let first = atoms.next()?;
+        atoms.all(|o| o == first).then_some(first)
+    }
+}
+
+fn spaces() -> &'static Spaces {
+    static SPACES: OnceLock<Spaces> = OnceLock::new();
+    SPACES.get_or_init(Spaces::builtin)
+}
+
+/// A new atom inside `parent`, added to every space that contains `parent`
+pub fn define_space(name: &str, parent: Space, occupancy: Occupancy) -> Result<Space, Error> {
+    let reg = spaces();
+    let mut w = reg.writer.lock().unwrap();
+    Spaces::check_name(&w, name)?;
+    let invalid = |reason| {
+        Err(Error::InvalidSpace {
+            name: name.to_string(),
+            reason,
+        })
+    };
+    if w.atoms == 64 {
+        return invalid("no atoms left");
+    }
+    let parent = reg.mask(parent);
+    if reg.occupancy(parent).is_some_and(|o| o != occupancy) {
+        return invalid("the occupancy differs from the parent space");
+    }
+    let bit = reg.push_atom(&mut w, occupancy);
+    for slot in &reg.slots[..w.len] {
+        if parent & !slot.mask.load(Ordering::Acquire) == 0 {
+            slot.mask.fetch_or(bit, Ordering::Release);
+        }
+    }
+    reg.push(&mut w, name, bit)
+}
+
+/// A new name for the orbitals of `parts`
+pub fn union_space(name: &str, parts: &[Space]) -> Result<Space, Error> {
+    let reg = spaces();
+    let mut w = reg.writer.lock().unwrap();
+    Spaces::check_name(&w, name)?;
+    let mask = parts.iter().fold(0, |m, &s| m | reg.mask(s));
+    reg.push(&mut w, name, mask)
+}
+
+/// Registered space with exactly the common orbitals of `a` and `b`
+pub fn intersect_spaces(a: Space, b: Space) -> Result<Option<Space>, Error> {
+    let reg = spaces();
+    let mask = reg.mask(a) & reg.mask(b);
+    if mask == 0 {
+        return Ok(None);
+    }
+    let mut w = reg.writer.lock().unwrap();
+    let found = reg.slots[..w.len]
+        .iter()
+        .position(|s| s.mask.load(Ordering::Acquire) == mask);
+    if let Some(k) = found {
+        return Ok(Some(Space::from_id(k as u16)));
+    }
+    let name = format!("{}&{}", a.name(), b.name());
+    reg.push(&mut w, &name, mask).map(Some)
+}
+
+pub fn lookup_space(name: &str) -> Option<Space> {
+    let w = spaces().writer.lock().unwrap();
+    w.names.get(name).map(|&id| Space::from_id(id))
+}
+
+pub fn space_data(space: Space) -> SpaceData {
+    let slot = &spaces().slots[space.id() as usize];
+    SpaceData {
+        name: slot.name.get().expect("unregistered space"),
+        mask: slot.mask.load(Ordering::Acquire),
+    }
+}
+
+pub fn space_mask(space: Space) -> u64 {
+    spaces().mask(space)
+}
+
+pub fn space_occupancy(space: Space) -> Option<Occupancy> {
+    let reg = spaces();
+    reg.occupancy(reg.mask(space))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attr::Vacuum;
+    use crate::index::Index;
+
+    #[test]
+    fn test_intern() {
+        let a = intern("reg_i");
+        let b = intern(&String::from("reg_i"));
+        assert!(std::ptr::eq(a, b));
+        let i = Index::new("reg_i").with_space(Space::Occupied);
+        let p = Index::new("reg_i");
+        assert_ne!(i, p);
+        assert_eq!(i, p.with_space(Space::Occupied));
+        assert!(std::ptr::eq(i.name(), a));
+        assert_eq!(i.with_vacuum(Vacuum::Fermi).vacuum(), Vacuum::Fermi);
+    }
+
+    #[test]
+    fn test_full_registry() {
+        // A private registry, filling the global one would starve other tests
+        let reg = Spaces::builtin();
+        let mut w = reg.writer.lock().unwrap();
+        let first = reg.push(&mut w, "t_full", 0b1).unwrap();
+        while w.len < MAX_SPACES {
+            reg.push(&mut w, "t_full", 0b1).unwrap();
+        }
+        assert!(matches!(
+            reg.push(&mut w, "t_full", 0b1),
+            Err(Error::InvalidSpace { .. })
+        ));
+        // The repeated name was leaked once
+        let last = reg.slots[MAX_SPACES - 1].name.get().unwrap();
+        assert!(std::ptr::eq(
+            *reg.slots[first.id() as usize].name.get().unwrap(),
+            *last
+        ));
+    }
+
+    #[test]
+    fn test_space_lattice() {
+        let active = Space::define("t_active_occ", Space::Occupied, Occupancy::Hole).unwrap();
+        let core = Space::define("t_core", Space::General, Occupancy::Hole).unwrap();
+        let all = Space::union("t_all_occ", &[core, active]).unwrap();
+        assert!(Space::Occupied.includes(active) && Space::General.includes(all));
+        assert!(all.includes(core) && !all.includes(Space::Occupied));
+        assert!(core.is_disjoint(Space::Occupied) && core.is_disjoint(Space::Virtual));
+        assert_eq!(Space::Occupied.intersection(all), Ok(Some(active)));
+        assert_eq!(core.intersection(Space::Virtual), Ok(None));
+        assert!(all.is_allowed(Vacuum::Fermi) && all.is_hole());
+        assert_eq!(Space::lookup("t_all_occ"), Some(all));
+        assert!(Space::define("t_core", Space::General, Occupancy::Hole).is_err());
+        assert!(Space::define("t_bad", Space::Virtual, Occupancy::Hole).is_err());
+
+        let mr = Space::define("t_mr", Space::General, Occupancy::Active).unwrap();
+        assert!(!mr.is_allowed(Vacuum::Fermi));
+        let e = crate::parser::parse("f[x:t_mr,y:t_mr] a+[x:t_mr] a[y:t_mr]").unwrap();
+        assert_eq!(e.terms()[0].ops[0].vacuum(), Vacuum::MultiReference);
+        assert_eq!(e.to_text(), "f[x:t_mr,y:t_mr] a+[x:t_mr] a[y:t_mr]");
+        // Core and active occupied orbitals never coincide
+        assert!(
+            crate::parser::parse("delta[x:t_core,y:t_active_occ]")
+                .unwrap()
+                .terms()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,415 @@
+/// src/repl.rs
+use crate::algebra::{commutator, normal_order, vev};
+use crate::attr::Space;
+use crate::error::Error;
+use crate::expr::Expr;
+use crate::format::Format;
+use crate::parser::{ParseError, parse_with_spaces};
+use crate::result_expr::ResultExpr;
+use std::collections::HashMap;
+
+pub const HELP: &str = "\
+statements:
+  index NAME.. SPACE   declare indices, SPACE is occ, vir, gen, core or
+                       the name of a defined space
+  let NAME = EXPR      bind an expression to a name
+  EXPR                 evaluate and print
+  format FORMAT        text, latex or unicode
+  history              print the session as a script
+  save FILE            write the session script to FILE
+  help                 print this message
+expressions:
+  {a+[p] a[q]}         a literal in the text syntax
+  NAME, 0.5, (EXPR)    bound names, numbers, grouping
+  A * B, A + B, A - B, -A
+  wick(A), vev(A), comm(A, B)";
+
+/// Interactive session: declared index spaces, bound expressions and the
+/// history of every statement that succeeded, replayable with `run_script`.
+#[derive(Debug, Clone)]
+pub struct Session {
+    spaces: HashMap<String, Space>,
+    bindings: HashMap<String, ResultExpr>,
+    format: Format,
+    history: Vec<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            spaces: HashMap::new(),
+            bindings: HashMap::new(),
+            format: Format::Text,
+            history: Vec::new(),
+        }
+    }
+
+    /// Run one statement and return what it prints
+    pub fn eval_line(&mut self, line: &str) -> Result<String, Error> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(String::new());
+        }
+        let (word, rest) = match line.split_once(char::is_whitespace) {
+            Some((w, r)) => (w, r.trim()),
+            None => (line, ""),
+        };
+        let out = match word {
+            "help" => return Ok(HELP.to_string()),
+            "history" => return Ok(self.script()),
+            "save" => {
+                if rest.is_empty() {
+                    return Err(repl_error("save expects a file name"));
+                }
+                std::fs::write(rest, self.script() + "\n")
+                    .map_err(|e| repl_error(format!("{}: {}", rest, e)))?;
+                return Ok(format!(
+                    "saved {} statements to {}",
+                    self.history.len(),
+                    rest
+                ));
+            }
+            "index" => {
+                let mut words: Vec<&str> = rest.split_whitespace().collect();
+                let space = match words.pop() {
+                    Some("occ") => Space::Occupied,
+                    Some("vir") => Space::Virtual,
+                    Some("gen") => Space::General,
+                    Some("core") => Space::DoublyOccupied,
+                    Some(s) => Space::lookup(s)
+                        .ok_or_else(|| repl_error(format!("unknown space {}", s)))?,
+                    None => return Err(repl_error("index expects NAME.. occ|vir|gen|core|SPACE")),
+                };
+                if words.is_empty() {
+                    return Err(repl_error("index expects at least one name"));
+                }
+                for w in words {
+                    self.spaces.insert(w.to_string(), space);
+                }
+                String::new()
+            }
+            "format" => {
+                self.format = match rest {
+                    "text" => Format::Text,
+                    "latex" => Format::Latex,
+                    "unicode" => Format::Unicode,
+                    f => return Err(repl_error(format!("unknown format {}", f))),
+                };
+                String::new()
+            }
+            "let" => {
+                let (name, expr) = rest
+                    .split_once('=')
+                    .ok_or_else(|| repl_error("let expects NAME = EXPR"))?;
+                let name = name.trim();
+                if !is_identifier(name) || is_function(name) {
+                    return Err(repl_error(format!("invalid name {}", name)));
+                }
+                let value = self.eval(expr)?;
+                let out = value.format(self.format);
+                self.bindings.insert(name.to_string(), value);
+                format!("{} = {}", name, out)
+            }
+            _ => self.eval(line)?.format(self.format),
+        };
+        self.history.push(line.to_string());
+        Ok(out)
+    }
+
+    /// Run every line of a script, stopping at the first error
+    pub fn run_script(&mut self, script: &str) -> Result<Vec<String>, Error> {
+        let mut out = Vec::new();
+        for (k, line) in script.lines().enumerate() {
+            let s = self.eval_line(line).map_err(|e| Error::Script {
+                line: k + 1,
+                error: Box::new(e),
+            })?;
+            if !s.is_empty() {
+                out.push(s);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Statements that succeeded so far, one per line
+    pub fn script(&self) -> String {
+        self.history.join("\n")
+    }
+
+    /// Some interface
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+    pub fn get(&self, name: &str) -> Option<&ResultExpr> {
+        self.bindings.get(name)
+    }
+
+    fn eval(&self, input: &str) -> Result<ResultExpr, Error> {
+        let mut p = ExprParser {
+            chars: input.chars().collect(),
+            pos: 0,
+            session: self,
+        };
+        let res = p.sum()?;
+        p.skip_ws();
+        if p.pos < p.chars.len() {
+            return Err(p.unexpected("an operator or end of input"));
+        }
+        Ok(res)
+    }
+}
+
+fn repl_error(message: impl Into<String>) -> Error {
+    Error::Repl {
+        message: message.into(),
+    }
+}
+
+fn syntax_error(pos: usize, message: impl Into<String>) -> Error {
+    Error::Parse(ParseError {
+        pos,
+        message: message.into(),
+    })
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic())
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn is_function(s: &str) -> bool {
+    matches!(s, "wick" | "vev" | "comm")
+}
+
+fn scale(a: ResultExpr, c: f64) -> ResultExpr {
+    a.into_iter()
+        .map(|t| {
+            let coeff = t.coeff * c;
+            t.set_coeff(coeff)
+        })
+        .collect()
+}
+
+/// A * B, letting terms without operators take the statistics of the other side
+fn product(a: ResultExpr, b: ResultExpr) -> Result<ResultExpr, Error> {
+    let mut res = ResultExpr::new();
+    for x in a.terms() {
+        for y in b.terms() {
+            let (mut x, mut y): (Expr, Expr) = (x.clone(), y.clone());
+            if x.ops.is_empty() {
+                x.statistic = y.statistic;
+            } else if y.ops.is_empty() {
+                y.statistic = x.statistic;
+            }
+            res.push_and_merge(x.try_mul(y)?);
+        }
+    }
+    res.simplify();
+    Ok(res)
+}
+
+/// Recursive descent over the session expression language
+struct ExprParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    session: &'a Session,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        let found = match self.peek() {
+            Some(c) => format!("'{}'", c),
+            None => "end of input".to_string(),
+        };
+        syntax_error(self.pos, format!("expected {}, found {}", expected, found))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        self.skip_ws();
+        if self.peek() != Some(c) {
+            return Err(self.unexpected(&format!("'{}'", c)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn sum(&mut self) -> Result<ResultExpr, Error> {
+        let mut res = self.product()?;
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some('+') => {
+                    self.pos += 1;
+                    res = res + self.product()?;
+                }
+                Some('-') => {
+                    self.pos += 1;
+                    res = res + scale(self.product()?, -1.0);
+                }
+                _ => break,
+            }
+        }
+        let mut merged = ResultExpr::new();
+        for t in res {
+            merged.push_and_merge(t);
+        }
+        merged.simplify();
+        Ok(merged)
+    }
+
+    fn product(&mut self) -> Result<ResultExpr, Error> {
+        let mut res = self.unary()?;
+        loop {
+            self.skip_ws();
+            if self.peek() != Some('*') {
+                return Ok(res);
+            }
+            self.pos += 1;
+            res = product(res, self.unary()?)?;
+        }
+    }
+
+    fn unary(&mut self) -> Result<ResultExpr, Error> {
+        self.skip_ws();
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            return Ok(scale(self.unary()?, -1.0));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<ResultExpr, Error> {
+        self.skip_ws();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let e = self.sum()?;
+                self.expect(')')?;
+                Ok(e)
+            }
+            Some('{') => {
+                let start = self.pos + 1;
+                let end = (start..self.chars.len())
+                    .find(|&k| self.chars[k] == '}')
+                    .ok_or_else(|| syntax_error(self.pos, "unclosed '{'"))?;
+                let text: String = self.chars[start..end].iter().collect();
+                self.pos = end + 1;
+                parse_with_spaces(&text, &self.session.spaces)
+                    .map_err(|e| syntax_error(start + e.pos, e.message))
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                let x: f64 = text
+                    .parse()
+                    .map_err(|_| syntax_error(start, format!("invalid number '{}'", text)))?;
+                Ok(ResultExpr::from_expr(Expr::new().set_coeff(x)))
+            }
+            Some(c) if c.is_alphabetic() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                if is_function(&name) {
+                    return self.call(&name);
+                }
+                self.session
+                    .bindings
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| repl_error(format!("unknown name {} at {}", name, start)))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<ResultExpr, Error> {
+        self.expect('(')?;
+        let mut args = vec![self.sum()?];
+        loop {
+            self.skip_ws();
+            if self.peek() != Some(',') {
+                break;
+            }
+            self.pos += 1;
+            args.push(self.sum()?);
+        }
+        self.expect(')')?;
+        let arity = if name == "comm" { 2 } else { 1 };
+        if args.len() != arity {
+            return Err(repl_error(format!(
+                "{} takes {} argument(s), found {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        match name {
+            "wick" => normal_order(&args[0]),
+            "vev" => vev(&args[0]),
+            _ => commutator(&args[0], &args[1]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let mut s = Session::new();
+        s.eval_line("let e = {a+[p] a[q]}").unwrap();
+        s.eval_line("let x = {a+[r]}").unwrap();
+        assert_eq!(s.eval_line("comm(e, x)").unwrap(), "delta[q,r] a+[p]");
+        assert_eq!(s.eval_line("2 * e - e + (x - x)").unwrap(), "a+[p] a[q]");
+        assert!(matches!(s.eval_line("e * y"), Err(Error::Repl { .. })));
+        assert!(matches!(
+            s.eval_line("e * {a[q}"),
+            Err(Error::Parse(ParseError { pos: 8, .. }))
+        ));
+        assert_eq!(s.history().len(), 4);
+
+        s.eval_line("index x y occ").unwrap();
+        assert_eq!(s.eval_line("vev({a+[x] a[y]})").unwrap(), "delta[x:o,y:o]");
+    }
+
+    #[test]
+    fn test_replay_history() {
+        let mut s = Session::new();
+        s.run_script("index u v vir\nlet t = {t[u,v]} * {a+[u] a[v]}\nformat latex\nt")
+            .unwrap();
+        let mut replay = Session::new();
+        let out = replay.run_script(&s.script()).unwrap();
+        assert_eq!(
+            out.last().unwrap(),
+            "t_{uv} \\hat a^\\dagger_{u} \\hat a_{v}"
+        );
+        assert_eq!(replay.script(), s.script());
+        assert!(matches!(
+            replay.run_script("t\nfoo"),
+            Err(Error::Script { line: 2, .. })
+        ));
+    }
+}
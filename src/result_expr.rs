@@ -1,20 +1,47 @@
 /// src/result_expr.rs
-use crate::expr::Expr;
+use crate::error::Error;
+use crate::expr::{Expr, Summation, TermKey};
+use crate::format::Format;
+use crate::index::Index;
+use std::collections::HashMap;
 use std::iter::FromIterator;
-use std::ops::Add;
+use std::ops::{Add, Mul};
 
-#[derive(Debug, Clone)]
+/// Sum of terms in insertion order. `keys` maps the key of the first
+/// `indexed` terms to their position, so merging a term is a hash lookup.
+/// Terms only change through methods that keep `keys` up to date, a
+/// deserialized sum has `indexed == 0` and is indexed on its first merge.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResultExpr {
-    pub terms: Vec<Expr>,
+    terms: Vec<Expr>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    keys: HashMap<TermKey, usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    indexed: usize,
 }
 
 impl ResultExpr {
     pub fn new() -> Self {
-        Self { terms: Vec::new() }
+        Self {
+            terms: Vec::new(),
+            keys: HashMap::new(),
+            indexed: 0,
+        }
+    }
+
+    /// Sum of `terms` as they are, without merging
+    pub fn from_terms(terms: Vec<Expr>) -> Self {
+        let mut res = Self::new();
+        res.terms = terms;
+        res.reindex();
+        res
     }
 
     pub fn from_expr(expr: Expr) -> Self {
-        Self { terms: vec![expr] }
+        let mut res = Self::new();
+        res.add_expr(expr);
+        res
     }
 
     pub fn add_expr(&mut self, expr: Expr) {
@@ -22,51 +49,135 @@ impl ResultExpr {
             return;
         }
         self.terms.push(expr);
+        // A duplicate key keeps pointing at its first term
+        if self.indexed + 1 == self.terms.len() {
+            let key = self.terms.last().unwrap().key();
+            self.keys.entry(key).or_insert(self.indexed);
+            self.indexed += 1;
+        }
     }
 
     pub fn to_latex(&self) -> String {
-        if self.terms.is_empty() {
-            return "0".to_string();
-        }
+        self.format(Format::Latex)
+    }
 
-        let mut s = String::new();
+    /// The terms in insertion order, read only so the key map stays valid
+    pub fn terms(&self) -> &[Expr] {
+        &self.terms
+    }
 
-        for (i, term) in self.terms.iter().enumerate() {
-            let term_tex = term.to_tensor_notation();
-            if term_tex.is_empty() || term_tex == "0" {
-                continue;
-            }
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
 
-            if i == 0 {
-                s.push_str(&term_tex);
-            } else {
-                if term.coeff > 0.0 {
-                    s.push_str(" + ");
-                    s.push_str(&term_tex);
-                } else {
-                    s.push_str(" ");
-                    s.push_str(&term_tex);
-                }
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn push_and_merge(&mut self, term: Expr) {
+        if term.coeff.abs() < 1e-15 || term.deltas.iter().any(|d| d.is_zero()) {
+            return;
+        }
+
+        if self.indexed != self.terms.len() {
+            self.reindex();
+        }
+        let key = term.key();
+        match self.keys.get(&key) {
+            Some(&k) => self.terms[k].coeff += term.coeff,
+            None => {
+                self.keys.insert(key, self.terms.len());
+                self.terms.push(term);
+                self.indexed += 1;
             }
         }
+    }
 
-        if s.is_empty() { "0".to_string() } else { s }
+    /// `Expr::relabel` on every term with the keys it contains, every key
+    /// has to show up in some term. Equal terms merge afterwards.
+    pub fn relabel(&self, map: &HashMap<Index, Index>) -> Result<ResultExpr, Error> {
+        let present = |t: &Expr, k: &Index| t.indices().any(|i| i == *k);
+        if let Some(&from) = map
+            .keys()
+            .find(|k| !self.terms.iter().any(|t| present(t, k)))
+        {
+            return Err(Error::InvalidRelabeling {
+                index: from,
+                reason: "it is not an index of the expression",
+            });
+        }
+        let mut res: ResultExpr = self
+            .terms
+            .iter()
+            .map(|t| {
+                let own: HashMap<Index, Index> = map
+                    .iter()
+                    .filter(|(k, _)| present(t, k))
+                    .map(|(&k, &v)| (k, v))
+                    .collect();
+                t.relabel(&own)
+            })
+            .collect::<Result<_, _>>()?;
+        res.simplify();
+        Ok(res)
     }
 
-    pub fn push_and_merge(&mut self, term: Expr) {
-        if term.coeff.abs() < 1e-15 {
-            return;
+    /// `Expr::substitute_index` on every term, vanishing terms are dropped
+    pub fn substitute_index(&self, from: Index, to: Index) -> Result<ResultExpr, Error> {
+        let mut res = ResultExpr::new();
+        for t in &self.terms {
+            if let Some(t) = t.substitute_index(from, to)? {
+                res.push_and_merge(t);
+            }
         }
+        res.simplify();
+        Ok(res)
+    }
 
-        if let Some(existing) = self.terms.iter_mut().find(|t| t.is_similar(&term)) {
-            existing.coeff += term.coeff;
-        } else {
-            self.terms.push(term);
+    /// Free indices shared by every term, in the order of the first term
+    pub fn free_indices(&self, summation: &Summation) -> Result<Vec<Index>, Error> {
+        let mut terms = self.terms.iter().map(|t| t.free_indices(summation));
+        let expected = terms.next().unwrap_or_default();
+        let sorted = |v: &[Index]| {
+            let mut v = v.to_vec();
+            v.sort_by_key(|i| i.name());
+            v
+        };
+        for (k, found) in terms.enumerate() {
+            if sorted(&found) != sorted(&expected) {
+                return Err(Error::UnbalancedIndices {
+                    term: k + 1,
+                    expected,
+                    found,
+                });
+            }
         }
+        Ok(expected)
+    }
+
+    /// Product of two sums, fails on mismatched statistics
+    pub fn try_mul(&self, rhs: &ResultExpr) -> Result<ResultExpr, Error> {
+        let mut res = ResultExpr::new();
+        for a in &self.terms {
+            for b in &rhs.terms {
+                res.push_and_merge(a.clone().try_mul(b.clone())?);
+            }
+        }
+        Ok(res)
     }
 
     pub fn simplify(&mut self) {
         self.terms.retain(|t| t.coeff.abs() > 1e-15);
+        self.reindex();
+    }
+
+    /// Rebuild the key map from `terms`, the first of equal terms wins
+    fn reindex(&mut self) {
+        self.keys.clear();
+        for (k, t) in self.terms.iter().enumerate() {
+            self.keys.entry(t.key()).or_insert(k);
+        }
+        self.indexed = self.terms.len();
     }
 }
 
@@ -101,6 +212,15 @@ impl Add<ResultExpr> for ResultExpr {
     }
 }
 
+// 4. ResultExpr * ResultExpr -> ResultExpr
+/// Panics on mismatched statistics, `try_mul` reports them instead
+impl Mul<ResultExpr> for ResultExpr {
+    type Output = ResultExpr;
+    fn mul(self, rhs: ResultExpr) -> Self::Output {
+        self.try_mul(&rhs).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
 impl FromIterator<Expr> for ResultExpr {
     fn from_iter<I: IntoIterator<Item = Expr>>(iter: I) -> Self {
         let mut result = ResultExpr::new();
@@ -119,15 +239,11 @@ impl IntoIterator for ResultExpr {
     }
 }
 
-impl Default for ResultExpr {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 #[cfg(test)]
 mod tests {
 
+    use crate::error::Error;
+    use crate::expr::Summation;
     use crate::index::Index;
     use crate::op::{fannx, fcrex};
 
@@ -142,6 +258,72 @@ mod tests {
         let expr2 = 3.0 * ap2 * cp1;
 
         let res = expr1 + expr2;
-        assert_eq!(res.to_latex(), "2a^{p1}_{p2} + 3a_{p2}a^{p1}");
+        assert_eq!(
+            res.to_latex(),
+            "2 \\hat a^\\dagger_{p_1} \\hat a_{p_2} + 3 \\hat a_{p_2} \\hat a^\\dagger_{p_1}"
+        );
+    }
+
+    #[test]
+    fn test_hash_merge() {
+        use crate::result_expr::ResultExpr;
+        use crate::tensor::Tensor;
+        let idx: Vec<Index> = (0..100)
+            .map(|k| Index::new(format!("p_{}", k)).build().unwrap())
+            .collect();
+        let mut res = ResultExpr::new();
+        for round in 0..2 {
+            for a in &idx {
+                for b in &idx[..50] {
+                    let t = Tensor::new("t", vec![*a, *b]);
+                    res.push_and_merge((1.0 + round as f64) * t);
+                }
+            }
+        }
+        assert_eq!(res.len(), 5000);
+        assert!(res.terms().iter().all(|t| t.coeff == 3.0));
+
+        // Dropped terms leave the index consistent
+        res.push_and_merge(-3.0 * Tensor::new("t", vec![idx[0], idx[0]]));
+        res.simplify();
+        res.push_and_merge(1.0 * Tensor::new("t", vec![idx[0], idx[1]]));
+        assert_eq!(res.len(), 4999);
+        assert_eq!(res.terms()[0].coeff, 4.0);
+    }
+
+    #[test]
+    fn test_merge_keeps_spaces() {
+        // Equal names in different spaces are different indices
+        let r = crate::parser::parse("f[x:o] + f[x:v]").unwrap();
+        assert_eq!(r.to_text(), "f[x:o] + f[x:v]");
+        let r = crate::parser::parse("delta[x:o,y:o] + delta[x:v,y:v]").unwrap();
+        assert_eq!(r.to_text(), "delta[x:o,y:o] + delta[x:v,y:v]");
+        let r = crate::parser::parse("f[x:o] + f[x:o]").unwrap();
+        assert_eq!(r.to_text(), "2 * f[x:o]");
+    }
+
+    #[test]
+    fn test_free_indices() {
+        let r = crate::parser::parse("f[i,a] + f[i,j] t[j,a] + 0.5 * g[i,b,j,c] t[j,b] t[c,a]")
+            .unwrap();
+        let free = r.free_indices(&Summation::Einstein).unwrap();
+        assert_eq!(
+            free.iter().map(|i| i.name()).collect::<Vec<_>>(),
+            ["i", "a"]
+        );
+        assert_eq!(
+            r.to_latex_summed(&Summation::Einstein),
+            "f_{ia} + \\sum_{j} f_{ij} t_{ja} + \\frac{1}{2} \\sum_{bjc} g_{ibjc} t_{jb} t_{ca}"
+        );
+
+        let bad = crate::parser::parse("f[i,a] + f[i,j] t[j,b]").unwrap();
+        assert!(matches!(
+            bad.free_indices(&Summation::Einstein),
+            Err(Error::UnbalancedIndices { term: 1, .. })
+        ));
+        // Summing over a as well leaves only i
+        let a = free[1];
+        let explicit = Summation::Explicit(vec![a]);
+        assert_eq!(r.terms()[0].free_indices(&explicit), vec![free[0]]);
     }
 }
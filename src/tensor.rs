@@ -0,0 +1,59 @@
+/// src/tensor.rs
+use crate::format::latex_index;
+use crate::index::Index;
+
+/// Tensor = label + ordered indices, e.g. f_{pq}, g_{pqrs}, t_{ijab}
+/// A tensor is a plain number factor (integral, amplitude, ...),
+/// so it commutes with every Op and every other Tensor in an Expr.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tensor {
+    pub label: String,
+    pub indices: Vec<Index>,
+}
+
+impl Tensor {
+    pub fn new(label: impl Into<String>, indices: Vec<Index>) -> Self {
+        Self {
+            label: label.into(),
+            indices,
+        }
+    }
+
+    /// Latex representation of the tensor, f_{pq}
+    pub fn to_latex(&self) -> String {
+        let idx: String = self.indices.iter().map(|i| latex_index(i.name())).collect();
+        format!("{}_{{{}}}", self.label, idx)
+    }
+
+    /// Key used to compare tensor products regardless of factor order,
+    /// indices with their space and vacuum
+    pub fn sort_key(&self) -> (&str, Vec<Index>) {
+        (self.label.as_str(), self.indices.clone())
+    }
+
+    /// Some interface
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+    pub fn indices(&self) -> &[Index] {
+        &self.indices
+    }
+    pub fn rank(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tensor_to_latex() {
+        let p1 = Index::new("p_1").build().unwrap();
+        let p2 = Index::new("p_2").build().unwrap();
+        let f = Tensor::new("f", vec![p1, p2]);
+        assert_eq!(f.rank(), 2);
+        assert_eq!(f.to_latex(), "f_{p_1p_2}");
+    }
+}
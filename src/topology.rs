@@ -0,0 +1,434 @@
+/// src/topology.rs
+use crate::attr::Space;
+use crate::cc::{canonicalize, cluster_products, external_name, factorial, occ, snap, vir};
+use crate::diagram::LineKind;
+use crate::expr::Expr;
+use crate::index::Index;
+use crate::permutation::permutations;
+use crate::result_expr::ResultExpr;
+use crate::tensor::Tensor;
+
+/// Vertex types of the diagrammatic generator
+/// Fock: f[p,q] {a+[p] a[q]}
+/// TwoBody: 1/4 g[p,q,r,s] {a+[p] a+[q] a[s] a[r]}
+/// Cluster(n): T_n = 1/(n!)^2 tn[a..,i..] a+[a_1]..a+[a_n] a[i_n]..a[i_1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VertexType {
+    Fock,
+    TwoBody,
+    Cluster(usize),
+}
+
+impl VertexType {
+    fn legs(self) -> usize {
+        match self {
+            Self::Fock => 1,
+            Self::TwoBody => 2,
+            Self::Cluster(n) => n,
+        }
+    }
+
+    fn coeff(self) -> f64 {
+        match self {
+            Self::Fock => 1.0,
+            Self::TwoBody => 0.25,
+            Self::Cluster(n) => 1.0 / (factorial(n) * factorial(n)),
+        }
+    }
+
+    fn label(self) -> String {
+        match self {
+            Self::Fock => "f".to_string(),
+            Self::TwoBody => "g".to_string(),
+            Self::Cluster(n) => format!("t{}", n),
+        }
+    }
+}
+
+/// Vertex of <m| H T.. |HF> in operator order. The projector is split into
+/// its legs, Hole(k) = a+[i_k] and Particle(k) = a[a_k], so that every
+/// external index stays distinguishable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Node {
+    Hole(usize),
+    Particle(usize),
+    Vertex(VertexType),
+}
+
+impl Node {
+    /// Creator and annihilator legs
+    fn legs(self) -> (usize, usize) {
+        match self {
+            Self::Hole(_) => (1, 0),
+            Self::Particle(_) => (0, 1),
+            Self::Vertex(v) => (v.legs(), v.legs()),
+        }
+    }
+}
+
+/// `count` lines from creators of node `from` to annihilators of node `to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: LineKind,
+    pub count: usize,
+}
+
+/// A connected Hugenholtz topology with the number of Wick pairings it
+/// stands for, summed over the relabelings of identical amplitudes
+#[derive(Debug, Clone)]
+pub struct Topology {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    pub pairings: usize,
+}
+
+/// Diagrammatic generator of <m| (H e^T)_c |HF>: the topologies are
+/// enumerated as line counts between vertices instead of Wick pairings, the
+/// weight is the pairing count divided by the vertex factors and the sign
+/// comes from the hole lines and loops of one Goldstone representative.
+pub struct Generator {
+    hamiltonian: Vec<VertexType>,
+    rank: usize,
+}
+
+impl Generator {
+    /// `hamiltonian`: Fock and/or TwoBody, `rank`: truncation of T
+    pub fn new(hamiltonian: Vec<VertexType>, rank: usize) -> Self {
+        Self { hamiltonian, rank }
+    }
+
+    /// Distinct connected topologies contributing to the excitation rank m
+    pub fn topologies(&self, m: usize) -> Vec<Topology> {
+        let mut res: Vec<(String, Topology)> = Vec::new();
+        for &h in &self.hamiltonian {
+            for ranks in cluster_products(self.rank, 2 * h.legs()) {
+                let mut nodes: Vec<Node> = (0..m).map(Node::Hole).collect();
+                nodes.extend((0..m).map(Node::Particle));
+                nodes.push(Node::Vertex(h));
+                nodes.extend(ranks.iter().map(|&k| Node::Vertex(VertexType::Cluster(k))));
+
+                let mut found = Vec::new();
+                let mut free: Vec<usize> = nodes.iter().map(|n| n.legs().1).collect();
+                assign(&nodes, 0, &mut free, &mut Vec::new(), &mut found);
+                for edges in found {
+                    if !connected(&nodes, &edges) {
+                        continue;
+                    }
+                    let key = canonical_key(&nodes, &edges);
+                    let pairings = pairing_count(&nodes, &edges);
+                    match res.iter_mut().find(|(k, _)| *k == key) {
+                        Some((_, t)) => t.pairings += pairings,
+                        None => res.push((
+                            key,
+                            Topology {
+                                nodes: nodes.clone(),
+                                edges,
+                                pairings,
+                            },
+                        )),
+                    }
+                }
+            }
+        }
+        res.into_iter().map(|(_, t)| t).collect()
+    }
+
+    /// Sum of the topologies, in the canonical form of `CoupledCluster`
+    pub fn projected(&self, m: usize) -> ResultExpr {
+        let mut res = ResultExpr::new();
+        for t in self.topologies(m) {
+            res.push_and_merge(canonicalize(t.term(), m));
+        }
+        snap(&mut res);
+        res
+    }
+}
+
+impl Topology {
+    /// (-1)^(hole lines + loops) of the representative
+    pub fn sign(&self) -> f64 {
+        let lines = self.lines();
+        let holes = lines.iter().filter(|l| l.4 == LineKind::Hole).count();
+        // A Goldstone point joins creator leg k and annihilator leg k of a
+        // vertex, the projector pairs a+[i_k] with a[a_k]
+        let point = |node: usize, leg: usize| match self.nodes[node] {
+            Node::Hole(k) | Node::Particle(k) => (usize::MAX, k),
+            Node::Vertex(_) => (node, leg),
+        };
+        let mut seen = vec![false; lines.len()];
+        let mut loops = 0;
+        for start in 0..lines.len() {
+            let mut k = start;
+            while !seen[k] {
+                seen[k] = true;
+                let end = point(lines[k].2, lines[k].3);
+                k = lines.iter().position(|l| point(l.0, l.1) == end).unwrap();
+                if k == start {
+                    loops += 1;
+                }
+            }
+        }
+        if (holes + loops).is_multiple_of(2) {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    /// Representative term: one tensor per vertex, coefficient
+    /// sign * vertex factors * 1/prod(m_k!) of e^T * pairings
+    pub fn term(&self) -> Expr {
+        let lines = self.lines();
+        let mut names: Vec<Index> = Vec::new();
+        let mut dummies = 0;
+        for &(from, _, to, _, kind) in &lines {
+            let index = match (self.nodes[from], self.nodes[to]) {
+                (Node::Hole(k), _) => occ(&external_name(Space::Occupied, k)),
+                (_, Node::Particle(k)) => vir(&external_name(Space::Virtual, k)),
+                _ => {
+                    dummies += 1;
+                    match kind {
+                        LineKind::Particle => vir(&format!("e{}", dummies)),
+                        _ => occ(&format!("m{}", dummies)),
+                    }
+                }
+            };
+            names.push(index);
+        }
+
+        let mut coeff = self.sign() * self.pairings as f64;
+        let mut tensors = Vec::new();
+        let mut amplitude_ranks = Vec::new();
+        for (v, node) in self.nodes.iter().enumerate() {
+            let Node::Vertex(t) = *node else { continue };
+            coeff *= t.coeff();
+            if let VertexType::Cluster(n) = t {
+                amplitude_ranks.push(n);
+            }
+            let n = t.legs();
+            let mut indices: Vec<Option<Index>> = vec![None; 2 * n];
+            for (l, line) in lines.iter().enumerate() {
+                if line.0 == v {
+                    indices[line.1] = Some(names[l]);
+                }
+                if line.2 == v {
+                    indices[n + line.3] = Some(names[l]);
+                }
+            }
+            tensors.push(Tensor::new(
+                t.label(),
+                indices.into_iter().map(|i| i.unwrap()).collect(),
+            ));
+        }
+        amplitude_ranks.sort();
+        for group in amplitude_ranks.chunk_by(|a, b| a == b) {
+            coeff /= factorial(group.len());
+        }
+
+        let mut e = Expr::new().set_coeff(coeff);
+        e.tensors = tensors;
+        e
+    }
+
+    /// Lines (from, creator leg, to, annihilator leg, kind), legs handed out
+    /// in edge order
+    fn lines(&self) -> Vec<(usize, usize, usize, usize, LineKind)> {
+        let mut cre = vec![0; self.nodes.len()];
+        let mut ann = vec![0; self.nodes.len()];
+        let mut lines = Vec::new();
+        for e in &self.edges {
+            for _ in 0..e.count {
+                lines.push((e.from, cre[e.from], e.to, ann[e.to], e.kind));
+                cre[e.from] += 1;
+                ann[e.to] += 1;
+            }
+        }
+        lines
+    }
+}
+
+/// Position in the operator string: projector, Hamiltonian, amplitudes
+fn position(nodes: &[Node], v: usize) -> usize {
+    match nodes[v] {
+        Node::Hole(_) | Node::Particle(_) => 0,
+        Node::Vertex(VertexType::Cluster(_)) => 2 + v,
+        Node::Vertex(_) => 1,
+    }
+}
+
+/// A line of `kind` from a creator of u to an annihilator of v. Particle
+/// lines contract a[a] with a later a+[b], hole lines a+[i] with a later a[j].
+fn allowed(nodes: &[Node], u: usize, v: usize, kind: LineKind) -> bool {
+    let creates = match nodes[u] {
+        Node::Hole(_) => kind == LineKind::Hole,
+        Node::Particle(_) => false,
+        Node::Vertex(VertexType::Cluster(_)) => kind == LineKind::Particle,
+        Node::Vertex(_) => true,
+    };
+    let annihilates = match nodes[v] {
+        Node::Hole(_) => false,
+        Node::Particle(_) => kind == LineKind::Particle,
+        Node::Vertex(VertexType::Cluster(_)) => kind == LineKind::Hole,
+        Node::Vertex(_) => true,
+    };
+    let (pu, pv) = (position(nodes, u), position(nodes, v));
+    let ordered = match kind {
+        LineKind::Particle => pv < pu,
+        _ => pu < pv,
+    };
+    u != v && creates && annihilates && ordered
+}
+
+/// Hand out the creator legs of node u, then of the following nodes
+fn assign(
+    nodes: &[Node],
+    u: usize,
+    free: &mut [usize],
+    edges: &mut Vec<Edge>,
+    out: &mut Vec<Vec<Edge>>,
+) {
+    if u == nodes.len() {
+        if free.iter().all(|&f| f == 0) {
+            out.push(edges.clone());
+        }
+        return;
+    }
+    let mut targets = Vec::new();
+    for v in 0..nodes.len() {
+        for kind in [LineKind::Particle, LineKind::Hole] {
+            if allowed(nodes, u, v, kind) {
+                targets.push((v, kind));
+            }
+        }
+    }
+    distribute(nodes, u, nodes[u].legs().0, &targets, free, edges, out);
+}
+
+fn distribute(
+    nodes: &[Node],
+    u: usize,
+    left: usize,
+    targets: &[(usize, LineKind)],
+    free: &mut [usize],
+    edges: &mut Vec<Edge>,
+    out: &mut Vec<Vec<Edge>>,
+) {
+    if left == 0 {
+        assign(nodes, u + 1, free, edges, out);
+        return;
+    }
+    let Some((&(v, kind), rest)) = targets.split_first() else {
+        return;
+    };
+    for count in (0..=left.min(free[v])).rev() {
+        free[v] -= count;
+        if count > 0 {
+            edges.push(Edge {
+                from: u,
+                to: v,
+                kind,
+                count,
+            });
+        }
+        distribute(nodes, u, left - count, rest, free, edges, out);
+        if count > 0 {
+            edges.pop();
+        }
+        free[v] += count;
+    }
+}
+
+/// Every amplitude shares a line with the Hamiltonian
+fn connected(nodes: &[Node], edges: &[Edge]) -> bool {
+    let h = nodes
+        .iter()
+        .position(|n| matches!(n, Node::Vertex(VertexType::Fock | VertexType::TwoBody)))
+        .unwrap();
+    nodes.iter().enumerate().all(|(v, n)| {
+        !matches!(n, Node::Vertex(VertexType::Cluster(_)))
+            || edges
+                .iter()
+                .any(|e| (e.from, e.to) == (h, v) || (e.from, e.to) == (v, h))
+    })
+}
+
+/// Pairings of one labelled topology: the legs of a vertex are
+/// interchangeable, prod(n_cre! n_ann!) / prod(count!)
+fn pairing_count(nodes: &[Node], edges: &[Edge]) -> usize {
+    let f = |n: usize| (1..=n).product::<usize>();
+    let legs: usize = nodes
+        .iter()
+        .map(|n| {
+            let (c, a) = n.legs();
+            f(c) * f(a)
+        })
+        .product();
+    legs / edges.iter().map(|e| f(e.count)).product::<usize>()
+}
+
+/// Smallest edge list over the relabelings of amplitudes of equal rank
+fn canonical_key(nodes: &[Node], edges: &[Edge]) -> String {
+    let mut perms: Vec<Vec<usize>> = vec![(0..nodes.len()).collect()];
+    let mut start = 0;
+    while start < nodes.len() {
+        let end = (start..nodes.len())
+            .find(|&k| nodes[k] != nodes[start])
+            .unwrap_or(nodes.len());
+        if matches!(nodes[start], Node::Vertex(VertexType::Cluster(_))) && end - start > 1 {
+            let mut next = Vec::new();
+            for p in &perms {
+                for order in permutations(&(start..end).collect::<Vec<_>>()) {
+                    let mut q = p.clone();
+                    q[start..end].copy_from_slice(&order);
+                    next.push(q);
+                }
+            }
+            perms = next;
+        }
+        start = end;
+    }
+    perms
+        .iter()
+        .map(|p| {
+            let mut key: Vec<(usize, usize, LineKind, usize)> = edges
+                .iter()
+                .map(|e| (p[e.from], p[e.to], e.kind, e.count))
+                .collect();
+            key.sort();
+            format!("{:?}", key)
+        })
+        .min()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cc::CoupledCluster;
+
+    fn sorted_terms(r: &ResultExpr) -> Vec<String> {
+        let mut v: Vec<String> = r.terms().iter().map(|t| t.to_text()).collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn test_ccsd_energy_topologies() {
+        let g = Generator::new(vec![VertexType::Fock, VertexType::TwoBody], 2);
+        // f t1, g t2, g t1 t1
+        assert_eq!(g.topologies(0).len(), 3);
+    }
+
+    #[test]
+    fn test_matches_algebraic() {
+        let cc = CoupledCluster::new(2);
+        for m in 0..=2 {
+            assert_eq!(
+                sorted_terms(&cc.diagrammatic(m)),
+                sorted_terms(&cc.residual(m))
+            );
+        }
+    }
+}
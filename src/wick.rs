@@ -1,212 +1,506 @@
 /// src/wick.rs
-use crate::attr::{Action, Statistics, Vacuum};
+use crate::attr::{Statistics, Vacuum};
+use crate::error::Error;
 use crate::expr::{Expr, is_normal_order};
 use crate::index::Index;
-use crate::op::{Delta, Op, can_contract};
+use crate::op::{Delta, Op, can_contract, is_quasi_annihilator};
 use crate::result_expr::ResultExpr;
 
 // Type aliases
-type IndexList = Vec<usize>;
-type Pairing = Vec<(usize, usize)>;
+pub type Pairing = Vec<(usize, usize)>;
+
+/// One contraction of the traced derivation: the pairing of operator
+/// positions, its transposition count and the sign and term it produced.
+#[derive(Debug, Clone)]
+pub struct WickStep {
+    pub pairing: Pairing,
+    /// Swaps of neighbouring operators bringing every pair together and the
+    /// uncontracted rest into normal order behind them, counted only up to
+    /// parity. The line crossings when every operator is contracted.
+    pub transpositions: usize,
+    pub sign: f64,
+    pub term: Expr,
+}
 
 pub struct WickTheorem {
     expr_: Expr,
     full_contractions_: bool,
+    trace_: bool,
+    #[cfg(feature = "parallel")]
+    threads_: usize,
+    steps_: Vec<WickStep>,
     wick_result_: ResultExpr,
     vacuum_: Vacuum,
     statistics_: Statistics,
 }
 
 impl WickTheorem {
-    pub fn new(expr: Expr) -> Self {
-        let vacuum = expr
-            .ops()
-            .first()
-            .map(|o| o.index.vacuum())
-            .unwrap_or(Vacuum::Physical);
-
+    /// Contractions of `expr` with respect to `vacuum`, usually
+    /// `expr.vacuum()?`. A vacuum the operators do not live on is only
+    /// reported by `compute` or `validate`, see `try_new` to fail early.
+    pub fn new(expr: Expr, vacuum: Vacuum) -> Self {
         let statistics = expr.statistic;
 
         Self {
             expr_: expr,
             full_contractions_: false,
+            trace_: false,
+            #[cfg(feature = "parallel")]
+            threads_: 1,
+            steps_: Vec::new(),
             wick_result_: ResultExpr::new(),
             vacuum_: vacuum,
             statistics_: statistics,
         }
     }
 
+    /// Like `new`, but validated right away, see `validate`
+    pub fn try_new(expr: Expr, vacuum: Vacuum) -> Result<Self, Error> {
+        let wt = Self::new(expr, vacuum);
+        wt.validate()?;
+        Ok(wt)
+    }
+
+    /// Vacuum the contractions refer to
+    pub fn set_vacuum(&mut self, vacuum: Vacuum) -> &mut Self {
+        self.vacuum_ = vacuum;
+        self
+    }
+
+    /// Placeholders must be substituted, every operator index must live on
+    /// the vacuum in a space it allows, and the vacuum must be one Wick's
+    /// theorem applies to
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(p) = self.expr_.placeholders().first() {
+            return Err(Error::UnexpandedPlaceholder {
+                name: p.name.clone(),
+            });
+        }
+        let positions: Vec<usize> = self
+            .expr_
+            .ops()
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.vacuum() != self.vacuum_ || !o.space().is_allowed(self.vacuum_))
+            .map(|(k, _)| k)
+            .collect();
+        if !positions.is_empty() {
+            return Err(Error::MixedVacua {
+                vacuum: self.vacuum_,
+                positions,
+            });
+        }
+        if self.vacuum_ == Vacuum::MultiReference && !self.expr_.ops().is_empty() {
+            return Err(Error::UnsupportedVacuum {
+                vacuum: self.vacuum_,
+                operation: "Wick's theorem",
+            });
+        }
+        Ok(())
+    }
+
     pub fn full_contractions(&mut self, full_contractions: bool) -> &mut Self {
         self.full_contractions_ = full_contractions;
         self
     }
 
-    pub fn compute(&mut self) -> &mut Self {
-        match (self.vacuum_, self.full_contractions_) {
-            (Vacuum::Physical, true) => self.wick_result_ = self.wick_expand_fc_pv(),
-            (Vacuum::Physical, false) => {
-                self.wick_result_ = self.wick_expand_pv(self.expr_.clone())
+    /// Record a WickStep for every term, see `trace_to_latex`
+    pub fn trace(&mut self, trace: bool) -> &mut Self {
+        self.trace_ = trace;
+        self
+    }
+
+    /// Worker threads of `compute`, the output does not depend on the count
+    #[cfg(feature = "parallel")]
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.threads_ = threads.max(1);
+        self
+    }
+
+    /// Expand the expression, fails when it does not pass `validate`
+    #[cfg(feature = "parallel")]
+    pub fn compute(&mut self) -> Result<&mut Self, Error> {
+        // A fixed split keeps the merge order independent of the thread count
+        const SUBTREES: usize = 256;
+        let parts = self.pairings()?.split(SUBTREES);
+        let next = std::sync::atomic::AtomicUsize::new(0);
+        let this = &*self;
+        let mut done: Vec<(usize, ResultExpr, Vec<WickStep>)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..this.threads_)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut out = Vec::new();
+                        loop {
+                            let k = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                            let Some(part) = parts.get(k) else {
+                                return out;
+                            };
+                            let mut result = ResultExpr::new();
+                            let mut steps = Vec::new();
+                            for p in part.clone() {
+                                let step = this.step(p);
+                                result.push_and_merge(step.term.clone());
+                                if this.trace_ {
+                                    steps.push(step);
+                                }
+                            }
+                            out.push((k, result, steps));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().unwrap())
+                .collect()
+        });
+        done.sort_by_key(|d| d.0);
+
+        let mut result = ResultExpr::new();
+        let mut steps = Vec::new();
+        for (_, r, s) in done {
+            for t in r {
+                result.push_and_merge(t);
             }
-            _ => {}
+            steps.extend(s);
         }
-        self
+        self.wick_result_ = result;
+        self.steps_ = steps;
+        Ok(self)
+    }
+
+    /// Expand the expression, fails when it does not pass `validate`
+    #[cfg(not(feature = "parallel"))]
+    pub fn compute(&mut self) -> Result<&mut Self, Error> {
+        let mut result = ResultExpr::new();
+        let mut steps = Vec::new();
+        for p in self.pairings()? {
+            let step = self.step(p);
+            result.push_and_merge(step.term.clone());
+            if self.trace_ {
+                steps.push(step);
+            }
+        }
+        self.wick_result_ = result;
+        self.steps_ = steps;
+        Ok(self)
     }
 
     pub fn to_latex(&self) -> String {
         self.wick_result_.to_latex()
     }
-}
-
-// --- Internal Implementation ---
 
-impl WickTheorem {
-    /// Full Wick contraction logic
-    fn wick_expand_fc_pv(&self) -> ResultExpr {
-        if self.expr_.ops().len() <= 1 || is_normal_order(&self.expr_) {
-            return ResultExpr::from_expr(self.expr_.clone());
-        }
+    /// Some interface
+    pub fn result(&self) -> &ResultExpr {
+        &self.wick_result_
+    }
+    pub fn vacuum(&self) -> Vacuum {
+        self.vacuum_
+    }
+    pub fn expr(&self) -> &Expr {
+        &self.expr_
+    }
+    pub fn steps(&self) -> &[WickStep] {
+        &self.steps_
+    }
 
-        let ops = &self.expr_.ops();
-        let num_create = ops
+    /// Traced steps as an aligned LaTeX block, one contraction per line,
+    /// drawn over the operator string with the `simplewick` package
+    pub fn trace_to_latex(&self) -> String {
+        let lines: Vec<String> = self
+            .steps_
             .iter()
-            .filter(|o| matches!(o.action(), Action::Create))
-            .count();
-        let num_annihilate = ops.len() - num_create;
+            .map(|s| {
+                // The sign is spelled out as (-1)^transpositions, keep it off the term
+                let c = s.term.coeff * s.sign;
+                format!(
+                    "{} &= (-1)^{{{}}} {}",
+                    s.to_latex(&self.expr_),
+                    s.transpositions,
+                    s.term.clone().set_coeff(c).to_latex()
+                )
+            })
+            .collect();
+        format!(
+            "\\begin{{aligned}}\n{}\n\\end{{aligned}}",
+            lines.join(" \\\\\n")
+        )
+    }
+}
 
-        // In full contraction, creation and annihilation counts must match
-        if num_create != num_annihilate {
-            return ResultExpr::new();
+impl WickStep {
+    /// Operator string of `expr` with one \contraction per pair, raised by
+    /// one level above every shorter pair it overlaps so brackets don't collide
+    pub fn to_latex(&self, expr: &Expr) -> String {
+        let ops: Vec<String> = expr
+            .ops
+            .iter()
+            .map(|o| o.to_latex(expr.statistic))
+            .collect();
+        let mut pairs: Vec<(usize, usize)> = self
+            .pairing
+            .iter()
+            .map(|&(i, j)| (i.min(j), i.max(j)))
+            .collect();
+        pairs.sort_by_key(|&(i, j)| (j - i, i));
+
+        let mut levels: Vec<usize> = Vec::new();
+        let mut s = String::new();
+        for (k, &(i, j)) in pairs.iter().enumerate() {
+            let level = 1 + pairs[..k]
+                .iter()
+                .zip(&levels)
+                .filter(|&(&(a, b), _)| a < j && i < b)
+                .map(|(_, &l)| l)
+                .max()
+                .unwrap_or(0);
+            levels.push(level);
+            s.push_str(&format!(
+                "\\contraction[{}ex]{{{}}}{{{}}}{{{}}}{{{}}}\n",
+                level,
+                ops[..i].join(" "),
+                ops[i],
+                ops[i + 1..j].join(" "),
+                ops[j]
+            ));
         }
+        s.push_str(&ops.join(" "));
+        s
+    }
+}
 
-        let indices: IndexList = (0..ops.len()).collect();
-        let pairings = generate_pairings(&self.expr_, &indices);
-
-        pairings
-            .into_iter()
-            .map(|p| {
-                let c = count_crossings(&p);
+/// Lazy depth-first enumeration of the contractions of an operator string.
+/// Every position is either left uncontracted (not in full mode) or paired,
+/// as a quasi-annihilator, with a quasi-creator to its right. The search runs
+/// on an explicit stack and yields one Pairing per call to `next`.
+#[derive(Clone)]
+pub struct Pairings<'a> {
+    ops: &'a [Op],
+    full: bool,
+    partner: Vec<Option<usize>>,
+    stack: Vec<Frame>,
+    // Decisions below this depth are fixed, the search stays in their subtree
+    floor: usize,
+    started: bool,
+    done: bool,
+}
 
-                let sign = match self.statistics_ {
-                    Statistics::FermiDirac if c % 2 != 0 => -1.0,
-                    _ => 1.0,
-                };
+/// Decision taken at position i: uncontracted, or paired with j
+#[derive(Clone)]
+struct Frame {
+    i: usize,
+    choice: Option<usize>,
+}
 
-                let mut term = Expr::new(); // Result of FC has no ops
-                term = term.set_coeff(sign * self.expr_.coeff());
+impl<'a> Pairings<'a> {
+    /// Partial contractions of `ops`, or only the full ones
+    pub fn new(ops: &'a [Op], full: bool) -> Self {
+        let creators = ops.iter().filter(|o| !is_quasi_annihilator(o)).count();
+        Self {
+            ops,
+            full,
+            partner: vec![None; ops.len()],
+            stack: Vec::new(),
+            floor: 0,
+            started: false,
+            // Full contraction needs as many quasi-creators as quasi-annihilators
+            done: full && 2 * creators != ops.len(),
+        }
+    }
 
-                for (i, j) in p {
-                    // Extract string indices from operators
-                    let idx_i = get_op_index(&self.expr_.ops[i]);
-                    let idx_j = get_op_index(&self.expr_.ops[j]);
-                    term.add_delta(Delta { a: idx_i, b: idx_j });
-                }
-                term
+    /// First option of position i after `after`: None is "uncontracted",
+    /// tried first, then the partners j in increasing order
+    fn option(&self, i: usize, after: Option<Option<usize>>) -> Option<Option<usize>> {
+        let start = match after {
+            None if !self.full => return Some(None),
+            None | Some(None) => i + 1,
+            Some(Some(j)) => j + 1,
+        };
+        (start..self.ops.len())
+            .find(|&j| {
+                self.partner[j].is_none()
+                    && is_quasi_annihilator(&self.ops[i])
+                    && can_contract(&self.ops[i], &self.ops[j])
             })
-            .collect()
+            .map(Some)
     }
 
-    fn wick_expand_pv(&self, e: Expr) -> ResultExpr {
-        if e.ops.len() <= 1 || is_normal_order(&e) {
-            return ResultExpr::from_expr(e);
+    fn push(&mut self, i: usize, choice: Option<usize>) {
+        if let Some(j) = choice {
+            self.partner[i] = Some(j);
+            self.partner[j] = Some(i);
         }
+        self.stack.push(Frame { i, choice });
+    }
 
-        // 遍历寻找可以收缩/交换的相邻对
-        for i in 0..e.ops.len() - 1 {
-            let a = &e.ops[i];
-            let b = &e.ops[i + 1];
-
-            if can_contract(a, b) {
-                let mut results = ResultExpr::new();
+    /// Undo decisions until one of them has another option
+    fn backtrack(&mut self) -> bool {
+        while self.stack.len() > self.floor {
+            let f = self.stack.pop().unwrap();
+            if let Some(j) = f.choice {
+                self.partner[f.i] = None;
+                self.partner[j] = None;
+            }
+            if let Some(c) = self.option(f.i, Some(f.choice)) {
+                self.push(f.i, c);
+                return true;
+            }
+        }
+        false
+    }
+}
 
-                // 1. 处理交换项 (Swapped Term)
-                let mut swapped = e.clone();
-                swapped.ops.swap(i, i + 1);
+#[cfg(feature = "parallel")]
+impl Pairings<'_> {
+    /// Subtrees of the next undecided position, None when every position is
+    /// decided
+    fn children(&self) -> Option<Vec<Self>> {
+        if self.done {
+            return Some(Vec::new());
+        }
+        let from = self.stack.last().map_or(0, |f| f.i + 1);
+        let i = (from..self.ops.len()).find(|&k| self.partner[k].is_none())?;
+        let mut res = Vec::new();
+        let mut option = self.option(i, None);
+        while let Some(c) = option {
+            let mut child = self.clone();
+            child.push(i, c);
+            child.floor = child.stack.len();
+            res.push(child);
+            option = self.option(i, Some(c));
+        }
+        Some(res)
+    }
 
-                if self.statistics_ == Statistics::FermiDirac {
-                    swapped.coeff *= -1.0;
+    /// Split the search into at least `target` subtrees when possible, in
+    /// the order of the sequential enumeration
+    fn split(self, target: usize) -> Vec<Self> {
+        let mut parts = vec![self];
+        while parts.len() < target {
+            let mut next = Vec::new();
+            let mut expanded = false;
+            for p in parts {
+                match p.children() {
+                    Some(c) => {
+                        expanded = true;
+                        next.extend(c);
+                    }
+                    None => next.push(p),
                 }
+            }
+            parts = next;
+            if !expanded {
+                break;
+            }
+        }
+        parts
+    }
+}
 
-                results = results + self.wick_expand_pv(swapped);
-
-                let mut contracted = e.clone();
-                contracted.add_delta(Delta {
-                    a: a.index.clone(),
-                    b: b.index.clone(),
-                });
-
-                if contracted.coeff.abs() > 1e-12 {
-                    contracted.ops.remove(i);
-                    contracted.ops.remove(i);
+impl Iterator for Pairings<'_> {
+    type Item = Pairing;
 
-                    results = results + self.wick_expand_pv(contracted);
+    fn next(&mut self) -> Option<Pairing> {
+        if self.done {
+            return None;
+        }
+        if self.started && !self.backtrack() {
+            self.done = true;
+            return None;
+        }
+        self.started = true;
+        loop {
+            let from = self.stack.last().map_or(0, |f| f.i + 1);
+            match (from..self.ops.len()).find(|&k| self.partner[k].is_none()) {
+                None => {
+                    return Some(
+                        self.stack
+                            .iter()
+                            .filter_map(|f| f.choice.map(|j| (f.i, j)))
+                            .collect(),
+                    );
                 }
-
-                return results;
+                Some(i) => match self.option(i, None) {
+                    Some(c) => self.push(i, c),
+                    None if self.backtrack() => {}
+                    None => {
+                        self.done = true;
+                        return None;
+                    }
+                },
             }
         }
-
-        ResultExpr::from_expr(e)
     }
 }
 
-/// Generates all possible full contractions (pairings) for a given expression.
-///
-/// This is a recursive back-tracking algorithm equivalent to the C++ template version.
-/// It follows the Fermi-Dirac statistics:
-/// 1. Takes the first available operator (at index `i`).
-/// 2. If it's a `Create` operator, it cannot initiate a contraction with operators to its right,
-///    so this branch returns empty (valid only for specific Wick orderings).
-/// 3. If it's an `Annihilate` operator, it tries to pair with every subsequent valid operator `j`.
-/// 4. Recursively processes the remaining indices until no operators are left.
-fn generate_pairings(e: &Expr, free_indices: &IndexList) -> Vec<Pairing> {
-    // Base case: No indices left to pair means we found one complete valid set of pairings.
-    if free_indices.is_empty() {
-        return vec![vec![]];
-    }
-
-    let mut results = Vec::new();
-
-    // Always pick the first index in the current sub-list to ensure a unique search path.
-    let i = free_indices[0];
-    let a = &e.ops[i];
-
-    // Contraction rule: In this specific implementation, we assume we are contracting
-    // an Annihilator with a Creator to its right.
-    if matches!(a.action(), Action::Create) {
-        return vec![];
-    }
-
-    // Attempt to pair index 'i' with every other index 'j' in the remaining list.
-    for k in 1..free_indices.len() {
-        let j = free_indices[k];
-        let b = &e.ops[j];
-
-        // Check if the physical contraction (e.g., a_i and a_j^dagger) is allowed.
-        if !can_contract(a, b) {
-            continue;
-        }
-
-        // Prepare the list of indices for the next recursion level.
-        // We filter out both the current 'i' (at position 0) and the current 'j' (at position k).
-        let rest: IndexList = free_indices
-            .iter()
-            .enumerate()
-            .filter(|&(idx, _)| idx != 0 && idx != k)
-            .map(|(_, &val)| val)
-            .collect();
+impl WickTheorem {
+    /// Lazy pairings of the operator string. A string already in normal order
+    /// only yields the empty pairing, even in full contraction mode.
+    pub fn pairings(&self) -> Result<Pairings<'_>, Error> {
+        self.validate()?;
+        let full = self.full_contractions_ && !is_normal_order(&self.expr_);
+        Ok(Pairings::new(&self.expr_.ops, full))
+    }
 
-        // Recursively find pairings for the remaining operators.
-        let sub_pairings = generate_pairings(e, &rest);
+    /// Terms of the expansion, yielded one by one without building the sum
+    pub fn terms(&self) -> Result<impl Iterator<Item = Expr> + '_, Error> {
+        Ok(self.pairings()?.map(|p| self.step(p).term))
+    }
 
-        // For each valid sub-pairing, prepend the current pair (i, j) to the results.
-        for sub in sub_pairings {
-            let mut p = Vec::with_capacity(sub.len() + 1);
-            p.push((i, j));
-            p.extend(sub);
-            results.push(p);
+    /// Term of one pairing: the deltas times the normal ordered rest, with
+    /// the sign of bringing every pair together and the rest in normal order.
+    /// Placeholders are not carried over, `pairings` rejects them first.
+    pub(crate) fn step(&self, p: Pairing) -> WickStep {
+        let ops = &self.expr_.ops;
+        let mut contracted = vec![false; ops.len()];
+        let mut order: Vec<usize> = Vec::with_capacity(ops.len());
+        for &(i, j) in &p {
+            contracted[i] = true;
+            contracted[j] = true;
+            order.extend([i, j]);
+        }
+        let paired = order.len();
+        let rest = (0..ops.len()).filter(|&k| !contracted[k]);
+        order.extend(rest.clone().filter(|&k| !is_quasi_annihilator(&ops[k])));
+        order.extend(rest.filter(|&k| is_quasi_annihilator(&ops[k])));
+
+        let inversions = |v: &[usize]| {
+            let mut n = 0;
+            for (k, a) in v.iter().enumerate() {
+                n += v[k + 1..].iter().filter(|b| *b < a).count();
+            }
+            n
+        };
+        // Pair inversions have the parity of the line crossings
+        let transpositions =
+            count_crossings(&p) + inversions(&order) - inversions(&order[..paired]);
+        let sign = match self.statistics_ {
+            Statistics::FermiDirac if !transpositions.is_multiple_of(2) => -1.0,
+            _ => 1.0,
+        };
+
+        let mut term = Expr::new()
+            .set_coeff(sign * self.expr_.coeff())
+            .set_statistic(self.expr_.statistic);
+        term.tensors = self.expr_.tensors.clone();
+        term.perms = self.expr_.perms.clone();
+        for d in &self.expr_.deltas {
+            term.add_delta(d.clone());
+        }
+        for &(i, j) in &p {
+            term.add_delta(Delta {
+                a: get_op_index(&ops[i]),
+                b: get_op_index(&ops[j]),
+            });
+        }
+        term.ops = order[paired..].iter().map(|&k| ops[k].clone()).collect();
+        WickStep {
+            pairing: p,
+            transpositions,
+            sign,
+            term,
         }
     }
-    results
 }
 
 /// Calculates the number of "line crossings" in a complete pairing set.
@@ -248,13 +542,58 @@ fn count_crossings(p: &Pairing) -> usize {
 }
 
 fn get_op_index(op: &Op) -> Index {
-    op.index.clone()
+    op.index
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::attr::Space;
     use crate::op::{fannx, fcrex};
+
+    #[test]
+    fn try_new_vacuum() {
+        let p = Index::new("p").build().unwrap();
+        let i = Index::new("i")
+            .with_space(Space::Occupied)
+            .with_vacuum(Vacuum::Fermi)
+            .build()
+            .unwrap();
+        assert_eq!(
+            WickTheorem::try_new(1.0 * fannx(p) * fcrex(i) * fannx(i), Vacuum::Physical).err(),
+            Some(Error::MixedVacua {
+                vacuum: Vacuum::Physical,
+                positions: vec![1, 2]
+            })
+        );
+        assert_eq!(
+            (1.0 * fannx(p) * fcrex(i)).vacuum(),
+            Err(Error::MixedVacua {
+                vacuum: Vacuum::Physical,
+                positions: vec![1]
+            })
+        );
+        let mut wt = WickTheorem::new(1.0 * fcrex(i) * fannx(i), Vacuum::Fermi);
+        assert!(wt.validate().is_ok());
+        // Occupied is not a space of the physical vacuum either
+        let bare = Index::new("i").with_space(Space::Occupied);
+        wt.set_vacuum(Vacuum::Physical);
+        assert!(wt.validate().is_err());
+        assert!(wt.compute().is_err());
+        assert!(WickTheorem::try_new(1.0 * fcrex(bare), Vacuum::Physical).is_err());
+        let mr = p.with_vacuum(Vacuum::MultiReference);
+        let mut wt = WickTheorem::new(1.0 * fannx(mr), Vacuum::MultiReference);
+        assert!(matches!(wt.compute(), Err(Error::UnsupportedVacuum { .. })));
+        // Placeholders have no place in the contracted terms
+        let mut h = 1.0 * fcrex(p) * fannx(p);
+        h.append_placeholder("H".to_string());
+        let wt = WickTheorem::new(h, Vacuum::Physical);
+        assert!(matches!(
+            wt.terms().err(),
+            Some(Error::UnexpandedPlaceholder { .. })
+        ));
+    }
+
     #[test]
     fn fermion_full_contraction() {
         let p1 = Index::new("p_1").build().unwrap();
@@ -268,11 +607,16 @@ mod test {
         let ap4 = fannx(p4);
         let expr = 1.0 * ap3 * ap4 * cp1 * cp2;
 
-        let wt = WickTheorem::new(expr)
+        let vacuum = expr.vacuum().unwrap();
+        let wt = WickTheorem::new(expr, vacuum)
             .full_contractions(true)
             .compute()
+            .unwrap()
             .to_latex();
-        assert_eq!(wt, "-s^{p3}_{p1}s^{p4}_{p2} + s^{p3}_{p2}s^{p4}_{p1}");
+        assert_eq!(
+            wt,
+            "-\\delta_{p_3p_1} \\delta_{p_4p_2} + \\delta_{p_3p_2} \\delta_{p_4p_1}"
+        );
     }
 
     #[test]
@@ -288,13 +632,93 @@ mod test {
         let ap4 = fannx(p4);
         let expr = 1.0 * ap3 * ap4 * cp1 * cp2;
 
-        let wt = WickTheorem::new(expr)
+        let vacuum = expr.vacuum().unwrap();
+        let wt = WickTheorem::new(expr, vacuum)
             .full_contractions(false)
             .compute()
+            .unwrap()
             .to_latex();
         assert_eq!(
             wt,
-            "a^{p1p2}_{p4p3} -s^{p3}_{p2}a^{p1}_{p4} + s^{p4}_{p2}a^{p1}_{p3} + s^{p3}_{p1}a^{p2}_{p4} -s^{p3}_{p1}s^{p4}_{p2} -s^{p4}_{p1}a^{p2}_{p3} + s^{p4}_{p1}s^{p3}_{p2}"
+            "\\hat a^\\dagger_{p_1} \\hat a^\\dagger_{p_2} \\hat a_{p_3} \\hat a_{p_4} - \\delta_{p_4p_1} \\hat a^\\dagger_{p_2} \\hat a_{p_3} + \\delta_{p_4p_2} \\hat a^\\dagger_{p_1} \\hat a_{p_3} + \\delta_{p_3p_1} \\hat a^\\dagger_{p_2} \\hat a_{p_4} - \\delta_{p_3p_1} \\delta_{p_4p_2} - \\delta_{p_3p_2} \\hat a^\\dagger_{p_1} \\hat a_{p_4} + \\delta_{p_3p_2} \\delta_{p_4p_1}"
+        );
+    }
+
+    #[test]
+    fn fermion_trace() {
+        let p: Vec<Index> = (1..=4)
+            .map(|k| Index::new(format!("p_{}", k)).build().unwrap())
+            .collect();
+        let expr = 1.0 * fannx(p[2]) * fannx(p[3]) * fcrex(p[0]) * fcrex(p[1]);
+
+        let vacuum = expr.vacuum().unwrap();
+        let mut wt = WickTheorem::new(expr, vacuum);
+        wt.full_contractions(true).trace(true).compute().unwrap();
+        let steps = wt.steps();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].pairing, vec![(0, 2), (1, 3)]);
+        assert_eq!((steps[0].transpositions, steps[0].sign), (1, -1.0));
+        assert_eq!((steps[1].transpositions, steps[1].sign), (0, 1.0));
+        assert_eq!(
+            steps[1].to_latex(&wt.expr_),
+            "\\contraction[1ex]{\\hat a_{p_3}}{\\hat a_{p_4}}{}{\\hat a^\\dagger_{p_1}}\n\\contraction[2ex]{}{\\hat a_{p_3}}{\\hat a_{p_4} \\hat a^\\dagger_{p_1}}{\\hat a^\\dagger_{p_2}}\n\\hat a_{p_3} \\hat a_{p_4} \\hat a^\\dagger_{p_1} \\hat a^\\dagger_{p_2}"
+        );
+        assert!(
+            wt.trace_to_latex()
+                .contains("&= (-1)^{1} \\delta_{p_3p_1} \\delta_{p_4p_2}")
+        );
+    }
+
+    #[test]
+    fn fermion_streaming() {
+        // a_1..a_6 a+_1..a+_6: 6! full contractions, sum_k C(6,k)^2 k! = 13327 terms in total
+        let idx: Vec<Index> = (1..=6)
+            .map(|k| Index::new(format!("p_{}", k)).build().unwrap())
+            .collect();
+        let mut expr = Expr::new();
+        for i in &idx {
+            expr.append_op(fannx(*i));
+        }
+        for i in &idx {
+            expr.append_op(fcrex(*i));
+        }
+        let vacuum = expr.vacuum().unwrap();
+        let mut wt = WickTheorem::new(expr, vacuum);
+        assert_eq!(wt.terms().unwrap().count(), 13327);
+        wt.full_contractions(true);
+        assert_eq!(wt.pairings().unwrap().count(), 720);
+        let positive = wt.terms().unwrap().filter(|t| t.coeff > 0.0).count();
+        assert_eq!(positive, 360);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn fermion_parallel() {
+        let idx: Vec<Index> = (1..=5)
+            .map(|k| Index::new(format!("p_{}", k)).build().unwrap())
+            .collect();
+        let mut expr = Expr::new();
+        for i in &idx {
+            expr.append_op(fannx(*i));
+        }
+        for i in idx.iter().rev() {
+            expr.append_op(fcrex(*i));
+        }
+        let vacuum = expr.vacuum().unwrap();
+        let mut wt = WickTheorem::new(expr, vacuum);
+        // The reference is merged term by term, without the subtree split
+        let mut reference = ResultExpr::new();
+        for t in wt.terms().unwrap() {
+            reference.push_and_merge(t);
+        }
+        assert_eq!(wt.compute().unwrap().to_latex(), reference.to_latex());
+        assert_eq!(
+            wt.threads(8).compute().unwrap().to_latex(),
+            reference.to_latex()
+        );
+        assert_eq!(
+            wt.threads(3).trace(true).compute().unwrap().steps().len(),
+            1546
         );
     }
 }